@@ -0,0 +1,195 @@
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use axum::http::{HeaderMap, header::AUTHORIZATION};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{configuration::AuthSettings, error::ApplicationError};
+
+/// The hex encoded SHA-256 digest of a bearer token, as configured in `auth.tokens`
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// The argon2 hash of a password with a random salt, as configured in `auth.users`
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Upper bound of the `VerifiedPasswords`, all of them are forgotten once it is reached
+const MAX_VERIFIED_PASSWORDS: usize = 1000;
+
+/// Digests of the user, password and hash combinations verified before
+/// Argon2 takes a while on purpose, so each password is only verified once per configured hash
+#[derive(Clone, Debug, Default)]
+pub struct VerifiedPasswords(Arc<Mutex<HashSet<String>>>);
+
+impl VerifiedPasswords {
+    fn lock(&self) -> MutexGuard<'_, HashSet<String>> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn verify(&self, name: &str, password: &str, password_hash: &str) -> bool {
+        // A changed hash leads to a different digest, so a changed password is verified again
+        let digest = hash_token(&format!("{name}:{password}:{password_hash}"));
+        if self.lock().contains(&digest) {
+            return true;
+        }
+        // Argon2 runs without the lock, so other requests are not held up by a slow verification
+        let valid = PasswordHash::new(password_hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        });
+        if valid {
+            let mut verified = self.lock();
+            if verified.len() >= MAX_VERIFIED_PASSWORDS {
+                verified.clear();
+            }
+            verified.insert(digest);
+        }
+        valid
+    }
+}
+
+/// The name of the token or user sent as `Authorization: Bearer <token>` or `Basic <credentials>`
+/// Verifying a password blocks for a while, so it is run in `spawn_blocking` by the handlers
+pub(crate) fn authenticate<'a>(
+    headers: &HeaderMap,
+    auth: &'a AuthSettings,
+    verified_passwords: &VerifiedPasswords,
+) -> Result<&'a str, ApplicationError> {
+    let authorization = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(ApplicationError::Unauthorized)?;
+    if let Some(token) = authorization.strip_prefix("Bearer ") {
+        let digest = hash_token(token);
+        auth.tokens
+            .iter()
+            .find(|credential| constant_time_eq(&credential.sha256.to_lowercase(), &digest))
+            .map(|credential| credential.name.as_str())
+            .ok_or(ApplicationError::Unauthorized)
+    } else if let Some(credentials) = authorization.strip_prefix("Basic ") {
+        let credentials = BASE64_STANDARD
+            .decode(credentials)
+            .ok()
+            .and_then(|credentials| String::from_utf8(credentials).ok())
+            .ok_or(ApplicationError::Unauthorized)?;
+        let (name, password) = credentials
+            .split_once(':')
+            .ok_or(ApplicationError::Unauthorized)?;
+        auth.users
+            .iter()
+            .find(|user| user.name == name)
+            .filter(|user| verified_passwords.verify(name, password, &user.password_hash))
+            .map(|user| user.name.as_str())
+            .ok_or(ApplicationError::Unauthorized)
+    } else {
+        Err(ApplicationError::Unauthorized)
+    }
+}
+
+/// Compare without returning early, so the time taken does not tell how much of a digest was right
+fn constant_time_eq(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue, header::AUTHORIZATION};
+    use base64::{Engine, prelude::BASE64_STANDARD};
+
+    use crate::{
+        auth::{VerifiedPasswords, authenticate, hash_password, hash_token},
+        configuration::{AuthSettings, TokenCredential, UserCredential},
+    };
+
+    fn auth() -> AuthSettings {
+        AuthSettings {
+            tokens: vec![
+                TokenCredential {
+                    name: "grafana".to_string(),
+                    sha256: hash_token("first secret"),
+                },
+                TokenCredential {
+                    name: "oncall".to_string(),
+                    sha256: hash_token("second secret"),
+                },
+            ],
+            users: vec![UserCredential {
+                name: "ops".to_string(),
+                password_hash: hash_password("Charlie 13").unwrap(),
+            }],
+            protected_endpoints: Vec::new(),
+        }
+    }
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        headers
+    }
+
+    fn basic(credentials: &str) -> HeaderMap {
+        headers(&format!("Basic {}", BASE64_STANDARD.encode(credentials)))
+    }
+
+    #[test]
+    fn the_token_names_its_owner() {
+        let auth = auth();
+        let verified = VerifiedPasswords::default();
+        assert_eq!(
+            authenticate(&headers("Bearer second secret"), &auth, &verified).unwrap(),
+            "oncall"
+        );
+    }
+
+    #[test]
+    fn users_are_authenticated_by_their_password() {
+        let auth = auth();
+        let verified = VerifiedPasswords::default();
+        assert_eq!(
+            authenticate(&basic("ops:Charlie 13"), &auth, &verified).unwrap(),
+            "ops"
+        );
+        assert!(authenticate(&basic("ops:Charlie 12"), &auth, &verified).is_err());
+        assert!(authenticate(&basic("grafana:Charlie 13"), &auth, &verified).is_err());
+    }
+
+    #[test]
+    fn missing_and_wrong_tokens_are_refused() {
+        let auth = auth();
+        let verified = VerifiedPasswords::default();
+        assert!(authenticate(&HeaderMap::new(), &auth, &verified).is_err());
+        assert!(authenticate(&headers("Bearer first secre"), &auth, &verified).is_err());
+        assert!(authenticate(&headers("Basic first secret"), &auth, &verified).is_err());
+        assert!(authenticate(&headers("Token first secret"), &auth, &verified).is_err());
+    }
+
+    #[test]
+    fn verified_passwords_are_only_remembered_for_their_hash() {
+        let mut auth = auth();
+        let verified = VerifiedPasswords::default();
+        assert!(authenticate(&basic("ops:Charlie 13"), &auth, &verified).is_ok());
+        assert!(authenticate(&basic("ops:Charlie 13"), &auth, &verified).is_ok());
+        assert!(authenticate(&basic("ops:Charlie 12"), &auth, &verified).is_err());
+        auth.users[0].password_hash = hash_password("Charlie 14").unwrap();
+        assert!(authenticate(&basic("ops:Charlie 13"), &auth, &verified).is_err());
+        assert!(authenticate(&basic("ops:Charlie 14"), &auth, &verified).is_ok());
+    }
+}
@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::{
+    configuration::DBConnectionPool,
+    database::{get_database_files, get_table_sizes},
+    error::ApplicationError,
+    model::mssql::{DatabaseFile, TableSize},
+    run::AppState,
+};
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// Database file usage and table sizes at a single point in time
+#[derive(Debug, Clone)]
+pub struct CapacitySample {
+    pub taken_at: Instant,
+    pub files: Vec<DatabaseFile>,
+    pub tables: Vec<TableSize>,
+}
+
+/// The periodic `CapacitySample`s used to fit the growth trend
+#[derive(Debug, Default)]
+pub struct CapacityHistory {
+    samples: VecDeque<CapacitySample>,
+}
+
+/// Projected capacity of a single data or log file
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct FileCapacity {
+    pub name: String,
+    pub file_type: String,
+    pub used_megabytes: i32,
+    /// The smaller one of the maximum file size and the size the file can reach on its disk
+    pub limit_megabytes: Option<i32>,
+    pub growth_megabytes_per_day: Option<f64>,
+    /// `None` if the file does not grow or has no known limit
+    pub days_until_full: Option<f64>,
+}
+
+/// Growth of a single big table and its share of the data file growth
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct TableGrowth {
+    pub table_name: String,
+    pub size_megabytes: i32,
+    pub growth_megabytes_per_day: Option<f64>,
+    pub growth_share_percentage: Option<f64>,
+}
+
+/// Growth forecast for the PW database
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default)]
+pub struct CapacityReport {
+    pub samples: usize,
+    pub files: Vec<FileCapacity>,
+    pub tables: Vec<TableGrowth>,
+}
+
+impl CapacityHistory {
+    /// Add a sample and drop every sample taken more than `history` before it
+    pub(crate) fn push(&mut self, sample: CapacitySample, history: Duration) {
+        let taken_at = sample.taken_at;
+        self.samples.push_back(sample);
+        while let Some(oldest) = self.samples.front()
+            && taken_at.duration_since(oldest.taken_at) > history
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Fit a linear growth trend through all samples of each file and table
+    pub(crate) fn report(&self) -> CapacityReport {
+        let Some(latest) = self.samples.back() else {
+            return CapacityReport::default();
+        };
+        let first_sample = self.samples.front().map(|sample| sample.taken_at);
+        let days = |sample: &CapacitySample| {
+            first_sample
+                .map(|first| sample.taken_at.duration_since(first).as_secs_f64() / SECONDS_PER_DAY)
+                .unwrap_or_default()
+        };
+
+        let files: Vec<FileCapacity> = latest
+            .files
+            .iter()
+            .map(|file| {
+                let points: Vec<(f64, f64)> = self
+                    .samples
+                    .iter()
+                    .filter_map(|sample| {
+                        sample
+                            .files
+                            .iter()
+                            .find(|sampled| sampled.name == file.name)
+                            .map(|sampled| (days(sample), sampled.used_megabytes.into()))
+                    })
+                    .collect();
+                let growth_megabytes_per_day = linear_slope(&points);
+                let limit_megabytes = file_limit(file);
+                FileCapacity {
+                    name: file.name.clone(),
+                    file_type: file.file_type.clone(),
+                    used_megabytes: file.used_megabytes,
+                    limit_megabytes,
+                    growth_megabytes_per_day,
+                    days_until_full: days_until_full(
+                        file.used_megabytes,
+                        limit_megabytes,
+                        growth_megabytes_per_day,
+                    ),
+                }
+            })
+            .collect();
+
+        let data_growth: f64 = files
+            .iter()
+            .filter(|file| file.file_type == "ROWS")
+            .filter_map(|file| file.growth_megabytes_per_day)
+            .sum();
+
+        let tables = latest
+            .tables
+            .iter()
+            .map(|table| {
+                let points: Vec<(f64, f64)> = self
+                    .samples
+                    .iter()
+                    .filter_map(|sample| {
+                        sample
+                            .tables
+                            .iter()
+                            .find(|sampled| sampled.table_name == table.table_name)
+                            .map(|sampled| (days(sample), sampled.size_megabytes.into()))
+                    })
+                    .collect();
+                let growth_megabytes_per_day = linear_slope(&points);
+                TableGrowth {
+                    table_name: table.table_name.clone(),
+                    size_megabytes: table.size_megabytes,
+                    growth_megabytes_per_day,
+                    growth_share_percentage: growth_megabytes_per_day
+                        .filter(|_| data_growth > 0.0)
+                        .map(|growth| growth / data_growth * 100.0),
+                }
+            })
+            .collect();
+
+        CapacityReport {
+            samples: self.samples.len(),
+            files,
+            tables,
+        }
+    }
+}
+
+/// The size a file can reach, limited by its maximum size and the free space on its disk
+fn file_limit(file: &DatabaseFile) -> Option<i32> {
+    let disk_limit = file
+        .volume_available_megabytes
+        .map(|available| file.size_megabytes + available);
+    match (file.max_size_megabytes, disk_limit) {
+        (Some(max_size), Some(disk_limit)) => Some(max_size.min(disk_limit)),
+        (max_size, disk_limit) => max_size.or(disk_limit),
+    }
+}
+
+/// Least squares slope of the `(x, y)` points
+/// `None` with less than two points or if all points were taken at the same time
+pub(crate) fn linear_slope(points: &[(f64, f64)]) -> Option<f64> {
+    if points.len() < 2 {
+        return None;
+    }
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / count;
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    if variance == 0.0 {
+        return None;
+    }
+    Some(covariance / variance)
+}
+
+/// Days until `used` reaches `limit` with the given daily growth
+pub(crate) fn days_until_full(
+    used: i32,
+    limit: Option<i32>,
+    growth_per_day: Option<f64>,
+) -> Option<f64> {
+    let limit = limit?;
+    let growth_per_day = growth_per_day.filter(|growth| *growth > 0.0)?;
+    Some((f64::from(limit - used) / growth_per_day).max(0.0))
+}
+
+#[tracing::instrument(name = "Take capacity sample", skip(pool))]
+async fn take_sample(pool: DBConnectionPool) -> Result<CapacitySample, ApplicationError> {
+    Ok(CapacitySample {
+        taken_at: Instant::now(),
+        files: get_database_files(pool.clone()).await?,
+        tables: get_table_sizes(pool).await?,
+    })
+}
+
+/// Periodically sample the database file usage and table sizes to forecast the growth
+pub async fn sample_capacity(state: Arc<Mutex<AppState>>, interval: Duration, history: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        // The lock is not held during the queries so requests are not blocked by the sampling
+        let pool = state.lock().await.pool.clone();
+        match take_sample(pool).await {
+            Ok(sample) => {
+                let mut state = state.lock().await;
+                state.capacity.push(sample, history);
+                let report = state.capacity.report();
+                state.metrics.set_capacity(&report);
+            }
+            Err(err) => warn!("Could not take capacity sample: {:?}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(used_megabytes: i32) -> DatabaseFile {
+        DatabaseFile {
+            name: "PWNT".to_string(),
+            file_type: "ROWS".to_string(),
+            size_megabytes: 1000,
+            used_megabytes,
+            max_size_megabytes: Some(2000),
+            volume_available_megabytes: Some(5000),
+        }
+    }
+
+    fn sample(
+        start: Instant,
+        day: u64,
+        used_megabytes: i32,
+        ev_log_megabytes: i32,
+    ) -> CapacitySample {
+        CapacitySample {
+            taken_at: start + Duration::from_secs(day * 86400),
+            files: vec![file(used_megabytes)],
+            tables: vec![TableSize {
+                table_name: "EV_LOG".to_string(),
+                size_megabytes: ev_log_megabytes,
+            }],
+        }
+    }
+
+    #[test]
+    fn slope_of_a_straight_line_is_exact() {
+        let slope = linear_slope(&[(0.0, 10.0), (1.0, 20.0), (2.0, 30.0)]);
+        assert_eq!(slope, Some(10.0));
+    }
+
+    #[test]
+    fn slope_needs_two_points_in_time() {
+        assert_eq!(linear_slope(&[(0.0, 10.0)]), None);
+        assert_eq!(linear_slope(&[(1.0, 10.0), (1.0, 20.0)]), None);
+    }
+
+    #[test]
+    fn shrinking_files_never_fill_up() {
+        assert_eq!(days_until_full(500, Some(1000), Some(-5.0)), None);
+        assert_eq!(days_until_full(500, Some(1000), Some(0.0)), None);
+        assert_eq!(days_until_full(500, None, Some(5.0)), None);
+    }
+
+    #[test]
+    fn days_until_full_are_projected() {
+        assert_eq!(days_until_full(500, Some(1000), Some(50.0)), Some(10.0));
+    }
+
+    #[test]
+    fn limit_is_the_smaller_of_max_size_and_disk() {
+        assert_eq!(file_limit(&file(0)), Some(2000));
+        assert_eq!(
+            file_limit(&DatabaseFile {
+                max_size_megabytes: None,
+                ..file(0)
+            }),
+            Some(6000)
+        );
+    }
+
+    #[test]
+    fn report_contains_growth_and_share() {
+        let mut history = CapacityHistory::default();
+        let keep = Duration::from_secs(30 * 86400);
+        let start = Instant::now();
+        history.push(sample(start, 0, 800, 100), keep);
+        history.push(sample(start, 1, 900, 150), keep);
+        history.push(sample(start, 2, 1000, 200), keep);
+
+        let report = history.report();
+        assert_eq!(report.samples, 3);
+        let growth = report.files[0].growth_megabytes_per_day.unwrap();
+        assert!((growth - 100.0).abs() < 0.01);
+        let days = report.files[0].days_until_full.unwrap();
+        assert!((days - 10.0).abs() < 0.01);
+        let share = report.tables[0].growth_share_percentage.unwrap();
+        assert!((share - 50.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn old_samples_are_dropped() {
+        let mut history = CapacityHistory::default();
+        let keep = Duration::from_secs(86400);
+        let start = Instant::now();
+        history.push(sample(start, 0, 800, 100), keep);
+        history.push(sample(start, 3, 1000, 200), keep);
+        assert_eq!(history.report().samples, 1);
+    }
+}
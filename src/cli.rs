@@ -0,0 +1,369 @@
+use anyhow::Context;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use tracing::info;
+
+use crate::{
+    auth::{hash_password, hash_token},
+    configuration::LimitSettings,
+    configuration::{ConfigWarnings, Settings, load_settings_with_warnings, resolve_config_files},
+    database::{DatabaseConnectionState, get_db_status, setup_database_pool},
+    handler::{encode_metrics, gather_health, refresh_metrics},
+    health::{PWHealth, health_is_good},
+    nagios::{NagiosState, nagios_report, plugin_output},
+    reload::ConfigSource,
+    run::{AppState, run},
+    telemetry::{initialize_command_tracing, initialize_tracing},
+    template::{default_config, migrate_config},
+};
+
+/// Log level of the one-shot commands unless `--log-level` is given, their logs go to standard error
+const COMMAND_LOG_LEVEL: &str = "warn";
+
+/// Service to monitor a PW-installation for its health
+///
+/// Exit codes: 0 on success, 1 if a check or command failed, 2 on invalid arguments
+#[derive(Parser, Debug)]
+#[command(name = "maedic", version)]
+pub struct Cli {
+    /// Config file or directory, can be given multiple times with later files taking precedence
+    #[arg(short, long = "config", value_name = "PATH", global = true)]
+    pub config: Vec<PathBuf>,
+
+    /// Log level overriding the configured `log_level`, e.g. debug or info,maedic=trace
+    #[arg(long, value_name = "LEVEL", global = true)]
+    pub log_level: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Start the service, the default without a subcommand
+    Serve,
+    /// Evaluate the health once and print it, exits with 1 if unhealthy
+    ///
+    /// With `--format nagios` a Nagios plugin line with perfdata is printed
+    /// and the exit code is 0/1/2/3 for OK/WARNING/CRITICAL/UNKNOWN
+    Check {
+        #[arg(long, value_enum, default_value_t = CheckFormat::Json)]
+        format: CheckFormat,
+
+        /// Share of a limit in percent from which a value is reported as WARNING in the nagios format
+        #[arg(long, value_name = "PERCENT", default_value_t = 80.0)]
+        warning_percentage: f32,
+    },
+    /// Collect the metrics once and print them in the Prometheus text format
+    Metrics,
+    /// Print the version
+    Version,
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Inspect the database connection
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Hash the credentials for the `auth` section
+    #[command(subcommand)]
+    Auth(AuthCommand),
+}
+
+/// Output formats of the `check` command
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum CheckFormat {
+    /// The `PWHealth` as returned by `/v1/health`
+    Json,
+    /// A Nagios/Icinga plugin line with perfdata
+    Nagios,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Load and validate the configuration without starting, exits with 1 if it is invalid
+    Validate,
+    /// Print the commented default configuration
+    PrintDefault,
+    /// Write the commented default configuration to a new config file
+    Init {
+        /// The config file to write
+        #[arg(default_value = "settings/base.yaml")]
+        path: PathBuf,
+
+        /// Overwrite an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Upgrade a config file of an older version, adding new keys with their default value
+    ///
+    /// The upgraded file is printed unless `--in-place` is given, the added, renamed and removed
+    /// keys are reported on standard error
+    Migrate {
+        /// The config file to upgrade
+        path: PathBuf,
+
+        /// Replace the config file, keeping the original as <PATH>.bak
+        #[arg(long)]
+        in_place: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Connect to the database once, exits with 1 if it is unreachable
+    TestConnection,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuthCommand {
+    /// Read a bearer token from standard input and print its SHA-256 digest for `auth.tokens`
+    HashToken,
+    /// Read a password from standard input and print its argon2 hash for `auth.users`
+    HashPassword,
+}
+
+impl Cli {
+    /// Run the selected command and return its exit code
+    pub async fn run(self) -> ExitCode {
+        let result = match &self.command {
+            None | Some(Command::Serve) => self.serve().await.map(|()| ExitCode::SUCCESS),
+            Some(Command::Check {
+                format: CheckFormat::Nagios,
+                warning_percentage,
+            }) => Ok(self.check_nagios(*warning_percentage).await),
+            Some(Command::Check { .. }) => self.check().await,
+            Some(Command::Metrics) => self.metrics().await,
+            Some(Command::Version) => {
+                println!("maedic version: {}", env!("CARGO_PKG_VERSION"));
+                Ok(ExitCode::SUCCESS)
+            }
+            Some(Command::Config(ConfigCommand::Validate)) => self.validate_config(),
+            Some(Command::Config(ConfigCommand::PrintDefault)) => {
+                print!("{}", default_config());
+                Ok(ExitCode::SUCCESS)
+            }
+            Some(Command::Config(ConfigCommand::Init { path, force })) => init_config(path, *force),
+            Some(Command::Config(ConfigCommand::Migrate { path, in_place })) => {
+                migrate_config_file(path, *in_place)
+            }
+            Some(Command::Db(DbCommand::TestConnection)) => self.test_connection().await,
+            Some(Command::Auth(command)) => hash_credential(command),
+        };
+        result.unwrap_or_else(|err| {
+            eprintln!("Error: {err:#}");
+            ExitCode::FAILURE
+        })
+    }
+
+    /// Resolve, load and validate the config files, applying the `--log-level`
+    /// Also returns the warnings about the config files, to be logged once tracing is set up
+    fn load_configuration(&self) -> anyhow::Result<(Vec<PathBuf>, Settings, ConfigWarnings)> {
+        let config_files = resolve_config_files(&self.config)?;
+        let (mut settings, warnings) = load_settings_with_warnings(&config_files)?;
+        if let Some(log_level) = &self.log_level {
+            settings.application.log_level = log_level.clone();
+        }
+        settings.validate()?;
+        Ok((config_files, settings, warnings))
+    }
+
+    /// Configuration and tracing for the one-shot commands
+    fn command_configuration(&self) -> anyhow::Result<Settings> {
+        let (_, settings, warnings) = self.load_configuration()?;
+        initialize_command_tracing(self.log_level.as_deref().unwrap_or(COMMAND_LOG_LEVEL))?;
+        warnings.log();
+        Ok(settings)
+    }
+
+    async fn serve(&self) -> anyhow::Result<()> {
+        let (config_files, configuration, warnings) = self.load_configuration()?;
+
+        let log_level = initialize_tracing(
+            configuration.application.log_level.clone(),
+            configuration.application.logfile_path.clone(),
+        )?;
+        info!(
+            "Configuration loaded from {}",
+            config_files
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        warnings.log();
+
+        let address = format!(
+            "{}:{}",
+            configuration.application.host, configuration.application.port
+        );
+        let listener = tokio::net::TcpListener::bind(&address)
+            .await
+            .with_context(|| format!("Could not bind to {address}"))?;
+
+        let state = AppState::new(
+            configuration.clone(),
+            ConfigSource {
+                files: config_files,
+                log_level: Some(log_level),
+                log_level_override: self.log_level.clone(),
+            },
+        )
+        .await?;
+
+        run(listener, state, configuration)
+            .await?
+            .await
+            .context("Failed to start application")?;
+        Ok(())
+    }
+
+    /// Gather the `PWHealth` once
+    async fn gather_health_once(&self) -> anyhow::Result<(PWHealth, LimitSettings)> {
+        let settings = self.command_configuration()?;
+        let mut state = AppState::new(settings, ConfigSource::default()).await?;
+        // The CPU usage is measured between two refreshes
+        tokio::time::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
+        let health = gather_health(&mut state).await?;
+        Ok((health, state.settings.limits))
+    }
+
+    async fn check(&self) -> anyhow::Result<ExitCode> {
+        let (health, limits) = self.gather_health_once().await?;
+        println!("{}", serde_json::to_string_pretty(&health)?);
+        Ok(if health_is_good(&health, &limits) {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        })
+    }
+
+    /// Any error, e.g. an invalid configuration, is reported as UNKNOWN
+    async fn check_nagios(&self, warning_percentage: f32) -> ExitCode {
+        let (state, output) = match self.gather_health_once().await {
+            Ok((health, limits)) => nagios_report(&health, &limits, warning_percentage),
+            Err(err) => (
+                NagiosState::Unknown,
+                plugin_output(NagiosState::Unknown, &format!("{err:#}"), &[]),
+            ),
+        };
+        println!("{output}");
+        ExitCode::from(state as u8)
+    }
+
+    async fn metrics(&self) -> anyhow::Result<ExitCode> {
+        let settings = self.command_configuration()?;
+        let state = AppState::new(settings, ConfigSource::default()).await?;
+        let collected = refresh_metrics(&state).await;
+        print!("{}", encode_metrics(&state));
+        if state.database_connection != DatabaseConnectionState::Healthy {
+            eprintln!(
+                "The database is unreachable, only the metrics of maedic itself were collected"
+            );
+            return Ok(ExitCode::FAILURE);
+        }
+        collected?;
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn validate_config(&self) -> anyhow::Result<ExitCode> {
+        let (config_files, _, warnings) = self.load_configuration()?;
+        for file in config_files {
+            println!("{}", file.display());
+        }
+        for key in warnings.unknown_keys {
+            eprintln!("Warning: unknown configuration field {key:?}");
+        }
+        for shadowed_limit in warnings.shadowed_limits {
+            eprintln!("Warning: {shadowed_limit}");
+        }
+        println!("Configuration is valid");
+        Ok(ExitCode::SUCCESS)
+    }
+
+    async fn test_connection(&self) -> anyhow::Result<ExitCode> {
+        let settings = self.command_configuration()?;
+        let pool = setup_database_pool(settings.database.clone())?;
+        match get_db_status(pool).await {
+            Ok(DatabaseConnectionState::Healthy) => {
+                println!(
+                    "Connected to the database {} on {}",
+                    settings.database.database_name, settings.database.hostname
+                );
+                Ok(ExitCode::SUCCESS)
+            }
+            Ok(DatabaseConnectionState::Unhealthy) => {
+                eprintln!("The database answered with an unexpected result");
+                Ok(ExitCode::FAILURE)
+            }
+            Err(err) => {
+                eprintln!("Could not connect to the database: {err}");
+                Ok(ExitCode::FAILURE)
+            }
+        }
+    }
+}
+
+/// Hash the first line of the standard input, so the secret never shows up in the shell history
+fn hash_credential(command: &AuthCommand) -> anyhow::Result<ExitCode> {
+    let mut secret = String::new();
+    std::io::stdin().read_line(&mut secret)?;
+    let secret = secret.trim_end_matches(['\r', '\n']);
+    if secret.is_empty() {
+        anyhow::bail!("Expected the secret on standard input");
+    }
+    let hash = match command {
+        AuthCommand::HashToken => hash_token(secret),
+        AuthCommand::HashPassword => {
+            hash_password(secret).map_err(|err| anyhow::anyhow!("Hashing the password: {err}"))?
+        }
+    };
+    println!("{hash}");
+    Ok(ExitCode::SUCCESS)
+}
+
+fn init_config(path: &Path, force: bool) -> anyhow::Result<ExitCode> {
+    if path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        );
+    }
+    if let Some(directory) = path.parent()
+        && !directory.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(directory)?;
+    }
+    std::fs::write(path, default_config())?;
+    println!("Default configuration written to {}", path.display());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn migrate_config_file(path: &Path, in_place: bool) -> anyhow::Result<ExitCode> {
+    let migration = migrate_config(path)?;
+    for key in &migration.added {
+        eprintln!("added {key} with its default value");
+    }
+    for (old_key, new_key) in &migration.renamed {
+        eprintln!("renamed {old_key} to {new_key}");
+    }
+    for key in &migration.removed {
+        eprintln!("removed unknown key {key}");
+    }
+    if in_place {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        std::fs::copy(path, &backup)?;
+        std::fs::write(path, &migration.config)?;
+        eprintln!(
+            "{} upgraded, the original is kept as {}",
+            path.display(),
+            PathBuf::from(backup).display()
+        );
+    } else {
+        print!("{}", migration.config);
+    }
+    Ok(ExitCode::SUCCESS)
+}
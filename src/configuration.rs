@@ -1,30 +1,282 @@
-use bb8::Pool;
-use bb8_tiberius::ConnectionManager;
+use bb8::{Pool, PooledConnection, RunError};
 use config::{Config, ConfigError};
+use prometheus_client::metrics::histogram::Histogram;
 use secrecy::SecretString;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, de};
 use serde_aux::field_attributes::deserialize_number_from_string;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{Span, warn};
+
+use crate::error::ApplicationError;
+use crate::failover::FailoverConnectionManager;
+use crate::health::HealthCheck;
+use crate::metrics::{Endpoint, QueryDurations, QueryLabel, QueryTimeouts};
+use crate::model::access_event::AccessResult;
 
 pub fn get_settings(name: String) -> Result<Settings, ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine current directory.");
     let settings_directory = base_path.join("settings");
 
-    Config::builder()
-        .add_source(config::File::from(settings_directory.join(name)).required(true))
-        .build()?
-        .try_deserialize()
+    load_settings(&[settings_directory.join(name)])
+}
+
+/// Names of the layered config files in a config directory, later files override earlier ones
+pub const CONFIG_LAYERS: [&str; 3] = ["base.yaml", "site.yaml", "local.yaml"];
+
+/// Build the `Settings` from the given files, later files override earlier ones
+/// An existing `application.limits_file` is loaded last, only the environment takes precedence over it
+/// A relative `limits_file` is resolved against the directory of the first config file
+/// Missing keys take their default value, unknown keys are ignored unless `application.strict_config` is set
+pub fn load_settings(files: &[PathBuf]) -> Result<Settings, ConfigError> {
+    load_settings_with_warnings(files).map(|(settings, _)| settings)
+}
+
+/// Warnings found while loading the config files
+/// They are returned instead of logged, as the settings are loaded before tracing is set up
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigWarnings {
+    /// Unknown keys, e.g. misspelled ones
+    pub unknown_keys: Vec<String>,
+    /// Limits of the config files overridden by a different value from the `limits_file`
+    pub shadowed_limits: Vec<String>,
+}
+
+impl ConfigWarnings {
+    /// Log the warnings, a misspelled key or a shadowed limit would otherwise silently have no effect
+    pub fn log(&self) {
+        for key in &self.unknown_keys {
+            warn!(
+                "Ignoring unknown configuration field {key:?}, set application.strict_config to refuse it"
+            );
+        }
+        for shadowed_limit in &self.shadowed_limits {
+            warn!("{shadowed_limit}");
+        }
+    }
+}
+
+/// Build the `Settings` like `load_settings`, also returning the `ConfigWarnings`
+///
+/// With `application.strict_config` any unknown key is refused.
+pub fn load_settings_with_warnings(
+    files: &[PathBuf],
+) -> Result<(Settings, ConfigWarnings), ConfigError> {
+    let builder = files.iter().fold(Config::builder(), |builder, file| {
+        builder.add_source(config::File::from(file.as_path()).required(true))
+    });
+    let mut config = builder
+        .clone()
+        .add_source(environment_overrides())
+        .build()?;
+    let limits_file = config
+        .get_string("application.limits_file")
+        .ok()
+        .filter(|limits_file| !limits_file.is_empty())
+        .map(|limits_file| resolve_limits_file(files, &limits_file));
+    let mut warnings = ConfigWarnings::default();
+    // The limits changed at runtime override the config files, but not the environment
+    if let Some(limits_file) = &limits_file
+        && limits_file.is_file()
+    {
+        let overridden = builder
+            .clone()
+            .add_source(config::File::from(limits_file.as_path()).required(true))
+            .add_source(environment_overrides())
+            .build()?;
+        warnings.shadowed_limits = shadowed_limits(&config, &overridden, limits_file);
+        config = overridden;
+    }
+    let mut settings: Settings =
+        serde_ignored::deserialize(config, |path| warnings.unknown_keys.push(path.to_string()))?;
+    if let Some(limits_file) = limits_file {
+        settings.application.limits_file = limits_file.display().to_string();
+    }
+    if settings.application.strict_config && !warnings.unknown_keys.is_empty() {
+        return Err(ConfigError::Message(format!(
+            "unknown configuration field {}",
+            warnings
+                .unknown_keys
+                .iter()
+                .map(|key| format!("{key:?}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    }
+    Ok((settings, warnings))
+}
+
+/// The `limits_file` relative to the directory of the first config file, like the config layers themselves
+fn resolve_limits_file(files: &[PathBuf], limits_file: &str) -> PathBuf {
+    match files.first().and_then(|file| file.parent()) {
+        Some(directory) => directory.join(limits_file),
+        None => PathBuf::from(limits_file),
+    }
+}
+
+/// The limits of the config files overridden by a different value from the `limits_file`
+/// Otherwise a later change to the config files would silently have no effect
+fn shadowed_limits(config: &Config, overridden: &Config, limits_file: &Path) -> Vec<String> {
+    let Ok(limits) = overridden.get_table("limits") else {
+        return Vec::new();
+    };
+    let mut shadowed_limits = Vec::new();
+    for key in limits.keys() {
+        let key = format!("limits.{key}");
+        if let (Ok(configured), Ok(persisted)) = (
+            config.get::<config::Value>(&key),
+            overridden.get::<config::Value>(&key),
+        ) && configured.to_string() != persisted.to_string()
+        {
+            shadowed_limits.push(format!(
+                "{key} of the config files is overridden with {persisted} from {}, remove it there to apply {configured}",
+                limits_file.display()
+            ));
+        }
+    }
+    shadowed_limits
+}
+
+/// Directories searched for the config files when no `--config` is given, in this order:
+/// `settings` in the current directory, `settings` next to the executable,
+/// `$XDG_CONFIG_HOME/maedic` (or `~/.config/maedic`) and `/etc/maedic`
+pub fn config_directories() -> Vec<PathBuf> {
+    let mut directories = Vec::new();
+    if let Ok(current_dir) = std::env::current_dir() {
+        directories.push(current_dir.join("settings"));
+    }
+    if let Some(executable_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|executable| executable.parent().map(Path::to_path_buf))
+    {
+        directories.push(executable_dir.join("settings"));
+    }
+    if let Some(config_home) = std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        directories.push(PathBuf::from(config_home).join("maedic"));
+    } else if let Some(home) = std::env::var_os("HOME") {
+        directories.push(PathBuf::from(home).join(".config").join("maedic"));
+    }
+    if cfg!(unix) {
+        directories.push(PathBuf::from("/etc/maedic"));
+    }
+    directories
+}
+
+/// The existing layers of a config directory, `base.yaml` is required
+fn directory_layers(directory: &Path) -> Result<Vec<PathBuf>, ConfigError> {
+    if !directory.join(CONFIG_LAYERS[0]).is_file() {
+        return Err(ConfigError::Message(format!(
+            "config directory {} does not contain a {}",
+            directory.display(),
+            CONFIG_LAYERS[0]
+        )));
+    }
+    Ok(CONFIG_LAYERS
+        .iter()
+        .map(|layer| directory.join(layer))
+        .filter(|file| file.is_file())
+        .collect())
+}
+
+/// Resolve the config files to load, later files override earlier ones
+///
+/// Each of the `paths` given via `--config` is either a file or a config directory holding the layers.
+/// Without any `paths` the layers of the first directory of `config_directories` containing a `base.yaml` are used.
+pub fn resolve_config_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, ConfigError> {
+    if paths.is_empty() {
+        let directories = config_directories();
+        return match directories
+            .iter()
+            .find(|directory| directory.join(CONFIG_LAYERS[0]).is_file())
+        {
+            Some(directory) => directory_layers(directory),
+            None => Err(ConfigError::Message(format!(
+                "no {} found in any of {}, use --config <path> to point to the config file",
+                CONFIG_LAYERS[0],
+                directories
+                    .iter()
+                    .map(|directory| directory.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+        };
+    }
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            files.extend(directory_layers(path)?);
+        } else if path.is_file() {
+            files.push(path.clone());
+        } else {
+            return Err(ConfigError::Message(format!(
+                "config file {} does not exist",
+                path.display()
+            )));
+        }
+    }
+    Ok(files)
+}
+
+/// Environment variables like `MAEDIC__DATABASE__PASSWORD` override the values of the config file
+fn environment_overrides() -> config::Environment {
+    config::Environment::with_prefix("MAEDIC")
+        .prefix_separator("__")
+        .separator("__")
+        // An unset variable passed on as an empty string must not blank out the config file
+        .ignore_empty(true)
+}
+
+/// Resolve a secret given as `env:<VARIABLE>` or `file:<path>`, any other value is taken literally
+fn resolve_secret(value: String) -> Result<SecretString, String> {
+    if let Some(variable) = value.strip_prefix("env:") {
+        std::env::var(variable)
+            .map(SecretString::from)
+            .map_err(|err| {
+                format!("could not read secret from environment variable {variable}: {err}")
+            })
+    } else if let Some(path) = value.strip_prefix("file:") {
+        std::fs::read_to_string(path)
+            // Secret files usually end with a newline that is not part of the secret
+            .map(|secret| SecretString::from(secret.trim_end_matches(['\r', '\n'])))
+            .map_err(|err| format!("could not read secret from file {path}: {err}"))
+    } else {
+        Ok(SecretString::from(value))
+    }
+}
+
+fn deserialize_secret<'de, D>(deserializer: D) -> Result<SecretString, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    resolve_secret(String::deserialize(deserializer)?).map_err(de::Error::custom)
+}
+
+fn deserialize_optional_secret<'de, D>(deserializer: D) -> Result<Option<SecretString>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(resolve_secret)
+        .transpose()
+        .map_err(de::Error::custom)
 }
 
 /// `Settings` collects the complete Options provided in the config file
+/// Each section and field is optional and takes its value from the `Default` impls
 #[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub limits: LimitSettings,
+    pub metrics: MetricSettings,
+    pub maintenance: MaintenanceSettings,
+    pub auth: AuthSettings,
 }
 
 /// Settings for the Application itself
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
@@ -34,34 +286,212 @@ pub struct ApplicationSettings {
     pub service_name: String,
     pub expose_config: bool,
     pub request_time_limit_seconds: u64,
+    /// Number of recent `AUDIT_LOG` changes exposed via `/v1/audit` with every changed column, 0 deactivates the endpoint
+    /// A change is a `BATCH_ID`, so more rows than this are returned
+    pub audit_log_entries: i32,
+    /// Replace the before and after images of the audit log entries
+    pub redact_audit_images: bool,
+    /// Refuse config files with unknown keys instead of logging a warning
+    pub strict_config: bool,
+    /// File the limits changed via `/v1/config/limits` are written to, it overrides the config files
+    /// Relative to the directory of the config files, empty keeps the changes until the next restart or reload
+    pub limits_file: String,
+    /// PEM file with the certificate chain served via HTTPS, empty serves plain HTTP
+    pub tls_certificate_file: String,
+    /// PEM file with the private key of the certificate
+    pub tls_key_file: String,
+    /// PEM file with the CAs a client certificate is verified against, empty does not ask for one
+    pub tls_client_ca_file: String,
+    /// Endpoints answered without a client certificate while there is a `tls_client_ca_file`, e.g. `health` for load balancers
+    /// Empty requires a client certificate for every endpoint
+    pub tls_anonymous_endpoints: Vec<Endpoint>,
 }
 
 /// Settings for the Database Connection Pool
 #[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
 pub struct DatabaseSettings {
+    /// Either a host or a named instance like `SQLSERVER\PROWATCH`
     pub hostname: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub auth_method: DBAuthMethod,
+    /// Required unless the `connection_string` or Integrated auth is used, there is no default login
     pub username: String,
-    #[serde(skip_serializing)]
+    /// Either the password itself, `env:<VARIABLE>` or `file:<path>`, required like the `username`
+    #[serde(skip_serializing, deserialize_with = "deserialize_secret")]
     pub password: SecretString,
     pub database_name: String,
     pub trust_cert: bool,
+    /// ADO.NET connection string, replacing the hostname, port, authentication, database and certificate settings
+    /// Resolved like the password
+    #[serde(
+        default,
+        skip_serializing,
+        deserialize_with = "deserialize_optional_secret"
+    )]
+    pub connection_string: Option<SecretString>,
+    /// Hosts holding a replica of the PW database, as `host`, `host:port` or a named instance
+    /// maedic connects to whichever host holds the primary replica
+    pub failover_partners: Vec<String>,
+    /// Try all hosts at once instead of one after another
+    pub multi_subnet_failover: bool,
+    /// Maximum number of connections held by the pool
+    pub pool_max_size: u32,
+    /// Number of idle connections the pool tries to keep open, 0 keeps none
+    pub pool_min_idle: u32,
+    /// Time to wait for a connection from the pool, including establishing a new one
+    pub connection_timeout_seconds: u64,
+    /// Idle connections are closed after this time, 0 keeps them open
+    pub idle_timeout_seconds: u64,
+    /// Connections are closed after this time, 0 keeps them open
+    pub max_lifetime_seconds: u64,
+    /// Time limit for each database query, 0 deactivates the limit
+    pub query_timeout_seconds: u64,
+    /// Interval between the checks of the database connection
+    pub connection_check_interval_seconds: u64,
+    /// Upper bound for the exponential backoff between reconnects to an unreachable database
+    pub reconnect_max_backoff_seconds: u64,
 }
 
 /// Limits for the `PWHealth` values
 /// Each numeric value is the maximum GOOD condition
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
 pub struct LimitSettings {
     pub hi_queue_count: i32,
     pub spool_file_count: i32,
     pub max_cpu_percentage: f32,
     pub max_ram_percentage: f32,
     pub check_local_service: bool,
+    /// Minimum number of workstations with an active PW client session
+    pub min_connected_workstations: i32,
+    /// Maximum number of failed operator logins within the configured window
+    pub max_failed_login_attempts: i32,
+    /// Maximum age of the oldest `EV_LOG` entry in days, i.e. the retention policy
+    pub max_event_log_age_days: i32,
+    /// Maximum days since the last event log archive run, a failed run always fails the check
+    pub max_days_since_last_archive: i32,
+    /// Maximum number of blocked sessions on the SQL Server
+    pub max_blocked_sessions: i32,
+    /// Maximum number of requests running longer than the configured `long_running_request_seconds`
+    pub max_long_running_requests: i32,
+    /// Maximum hours since the last full backup of the PW database
+    pub max_full_backup_age_hours: i32,
+    /// Maximum hours since the last differential backup of the PW database
+    pub max_differential_backup_age_hours: i32,
+    /// Maximum hours since the last log backup of the PW database
+    pub max_log_backup_age_hours: i32,
+    /// Fail if a replica of the PW database in an availability group is not synchronized
+    pub check_replica_synchronization: bool,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+/// Settings for the metrics collected from the database
+/// Set any value to 0 to deactivate the collection
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct MetricSettings {
+    /// Size of the sliding window for the access events in minutes
+    pub access_event_window_minutes: i32,
+    /// Number of logical devices and panels exported individually, the rest is summed up as "other"
+    pub access_event_top_n: i32,
+    /// `EV_LOG.EVNT_TYPE` values of granted accesses, the access events are only classified by their
+    /// English `EVNT_DESCRP` if no event types are configured at all
+    pub granted_event_types: Vec<String>,
+    /// `EV_LOG.EVNT_TYPE` values of denied accesses
+    pub denied_event_types: Vec<String>,
+    /// `EV_LOG.EVNT_TYPE` values of invalid cards, taking precedence over the denied and granted ones
+    pub invalid_card_event_types: Vec<String>,
+    /// Minutes after which a PW client session without update is considered stale
+    /// Also used to determine the connected workstations for the health check
+    pub session_stale_minutes: i32,
+    /// Names of the workstations monitoring alarms, only those count as connected for the health check
+    /// None counts every workstation with an active PW client session, including admin and badging clients
+    pub alarm_workstations: Vec<String>,
+    /// Days after which a workstation without login is considered inactive
+    pub workstation_inactive_days: i32,
+    /// Number of host and program combinations exported with their connections, the rest is summed up as "other"
+    pub database_session_top_n: i32,
+    /// Size of the sliding window for the failed operator logins in minutes
+    /// Also used for the failed login health check, so it must not be 0 while that check is active
+    pub failed_login_window_minutes: i32,
+    /// Size of the sliding window for the configuration changes from the audit log in minutes
+    pub audit_window_minutes: i32,
+    /// Seconds after which a request on the SQL Server is considered long-running
+    /// Also used for the long-running requests health check
+    pub long_running_request_seconds: i32,
+    /// Number of wait types with the highest accumulated wait time exported
+    pub wait_stats_top_n: i32,
+    /// Interval between the samples for the database growth forecast in minutes
+    pub capacity_sample_interval_minutes: i32,
+    /// Days of samples used to fit the database growth trend
+    pub capacity_history_days: i32,
+}
+
+/// Credentials for the API, the `protected_endpoints` and every change require one of them
+/// Without any credentials the changes via `/v1/config/limits` and `/v1/silences` are deactivated
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct AuthSettings {
+    /// Tokens sent as `Authorization: Bearer <token>`
+    pub tokens: Vec<TokenCredential>,
+    /// Users sent as `Authorization: Basic <base64 of name:password>`
+    pub users: Vec<UserCredential>,
+    /// Endpoints which are only answered with valid credentials, all others are open to anyone
+    pub protected_endpoints: Vec<Endpoint>,
+}
+
+/// A bearer token, only its SHA-256 digest from `maedic auth hash-token` is configured
+/// The `name` is logged as the author of a change
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct TokenCredential {
+    pub name: String,
+    /// Hex encoded SHA-256 digest of the token
+    pub sha256: String,
+}
+
+/// A user for HTTP basic auth, only its password hash from `maedic auth hash-password` is configured
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct UserCredential {
+    pub name: String,
+    /// Argon2 hash of the password in the PHC string format, e.g. `$argon2id$v=19$...`
+    pub password_hash: String,
+}
+
+/// Planned maintenance during which failed checks do not affect the status code
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct MaintenanceSettings {
+    pub windows: Vec<MaintenanceWindow>,
+}
+
+/// A recurring or one-off maintenance window silencing the failed checks in its scope
+/// Either a `schedule` with a `duration_minutes` or a `start` and an `end` is required
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct MaintenanceWindow {
+    /// Name of the window, exported with the `maedic_maintenance_window_active` metric
+    pub name: String,
+    /// Cron expression in local time opening the window, e.g. `0 22 * * SAT`
+    pub schedule: Option<String>,
+    /// Length of each window opened by the `schedule`
+    pub duration_minutes: u64,
+    /// Begin of a one-off window as RFC 3339 timestamp, e.g. `2026-11-01T20:00:00+01:00`
+    pub start: Option<String>,
+    /// End of a one-off window as RFC 3339 timestamp
+    pub end: Option<String>,
+    /// Silenced checks, none silences every check
+    pub checks: Vec<HealthCheck>,
+    /// Silenced channels of the `spool_files` check by their description
+    pub channels: Vec<String>,
+    /// Silenced panels by their description, covering the `spool_files` of their channels and their `hi_queue_size`
+    /// Without channels and panels every channel and panel is silenced
+    pub panels: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub enum DBAuthMethod {
     Basic,
     Windows,
@@ -72,12 +502,23 @@ impl Default for DatabaseSettings {
     fn default() -> Self {
         Self {
             port: 1433,
-            hostname: "0.0.0.0".to_string(),
-            username: "sa".into(),
+            hostname: "localhost".to_string(),
+            username: String::new(),
             auth_method: DBAuthMethod::Basic,
-            password: "Charlie 13".into(),
+            password: SecretString::default(),
             database_name: "PWNT".into(),
             trust_cert: true,
+            connection_string: None,
+            failover_partners: Vec::new(),
+            multi_subnet_failover: false,
+            pool_max_size: 5,
+            pool_min_idle: 0,
+            connection_timeout_seconds: 5,
+            idle_timeout_seconds: 600,
+            max_lifetime_seconds: 1800,
+            query_timeout_seconds: 5,
+            connection_check_interval_seconds: 10,
+            reconnect_max_backoff_seconds: 60,
         }
     }
 }
@@ -90,6 +531,49 @@ impl Default for LimitSettings {
             max_cpu_percentage: 80.0,
             max_ram_percentage: 80.0,
             check_local_service: false,
+            min_connected_workstations: 0,
+            max_failed_login_attempts: 0,
+            max_event_log_age_days: 0,
+            max_days_since_last_archive: 0,
+            max_blocked_sessions: 0,
+            max_long_running_requests: 0,
+            max_full_backup_age_hours: 0,
+            max_differential_backup_age_hours: 0,
+            max_log_backup_age_hours: 0,
+            check_replica_synchronization: false,
+        }
+    }
+}
+
+impl MetricSettings {
+    /// The configured `EV_LOG.EVNT_TYPE` values per access result, in the order they are matched
+    pub(crate) fn access_event_types(&self) -> [(AccessResult, &[String]); 3] {
+        [
+            (AccessResult::InvalidCard, &self.invalid_card_event_types),
+            (AccessResult::Denied, &self.denied_event_types),
+            (AccessResult::Granted, &self.granted_event_types),
+        ]
+    }
+}
+
+impl Default for MetricSettings {
+    fn default() -> Self {
+        Self {
+            access_event_window_minutes: 15,
+            access_event_top_n: 20,
+            granted_event_types: Vec::new(),
+            denied_event_types: Vec::new(),
+            invalid_card_event_types: Vec::new(),
+            session_stale_minutes: 10,
+            alarm_workstations: Vec::new(),
+            workstation_inactive_days: 30,
+            database_session_top_n: 20,
+            failed_login_window_minutes: 60,
+            audit_window_minutes: 60,
+            long_running_request_seconds: 30,
+            wait_stats_top_n: 10,
+            capacity_sample_interval_minutes: 60,
+            capacity_history_days: 30,
         }
     }
 }
@@ -104,16 +588,90 @@ impl Default for ApplicationSettings {
             service_name: "micserver.exe".into(),
             expose_config: false,
             request_time_limit_seconds: 5,
+            audit_log_entries: 0,
+            redact_audit_images: true,
+            strict_config: false,
+            limits_file: String::new(),
+            tls_certificate_file: String::new(),
+            tls_key_file: String::new(),
+            tls_client_ca_file: String::new(),
+            tls_anonymous_endpoints: Vec::new(),
         }
     }
 }
 
-pub type DBConnectionPool = Pool<ConnectionManager>;
+/// The bb8 connection pool together with the time limit applied to each query
+#[derive(Clone, Debug)]
+pub struct DBConnectionPool {
+    pub(crate) pool: Pool<FailoverConnectionManager>,
+    pub(crate) query_timeout: Option<Duration>,
+    pub(crate) query_durations: QueryDurations,
+    pub(crate) query_timeouts: QueryTimeouts,
+}
+
+impl DBConnectionPool {
+    pub(crate) async fn get(
+        &self,
+    ) -> Result<PooledConnection<'_, FailoverConnectionManager>, RunError<bb8_tiberius::Error>>
+    {
+        self.pool.get().await
+    }
+
+    pub(crate) fn state(&self) -> bb8::State {
+        self.pool.state()
+    }
+
+    /// Run a query, failing with `ApplicationError::QueryTimeout` once the query timeout elapsed
+    /// The latency and the timeouts are recorded under the name of the current tracing span
+    pub(crate) async fn timed<T>(
+        &self,
+        query: impl Future<Output = Result<T, ApplicationError>>,
+    ) -> Result<T, ApplicationError> {
+        let label = QueryLabel {
+            query: Span::current()
+                .metadata()
+                .map_or("unknown", |metadata| metadata.name())
+                .to_string(),
+        };
+        // Observed on drop, so queries cancelled by the request time limit are recorded as well
+        let _timer = QueryTimer {
+            histogram: self.query_durations.get_or_create(&label).clone(),
+            started: Instant::now(),
+        };
+        let Some(query_timeout) = self.query_timeout else {
+            return query.await;
+        };
+        tokio::time::timeout(query_timeout, query)
+            .await
+            .unwrap_or_else(|_| {
+                self.query_timeouts.get_or_create(&label).inc();
+                Err(ApplicationError::QueryTimeout)
+            })
+    }
+}
+
+/// Records the duration of a query once it finished, failed, timed out or was dropped
+struct QueryTimer {
+    histogram: Histogram,
+    started: Instant,
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        self.histogram.observe(self.started.elapsed().as_secs_f64());
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use crate::configuration::get_settings;
+    use crate::configuration::{
+        ConfigWarnings, LimitSettings, MetricSettings, Settings, environment_overrides,
+        get_settings, load_settings, load_settings_with_warnings, resolve_config_files,
+        resolve_secret,
+    };
+    use config::{Config, File, FileFormat};
     use rstest::rstest;
+    use secrecy::ExposeSecret;
 
     #[rstest]
     #[case("test")]
@@ -125,15 +683,174 @@ mod tests {
 
     #[test]
     fn test_application_exits_on_bad_config() {
-        let config = get_settings("../tests/bad_config.yaml".to_string());
-        dbg!(&config);
-        assert!(config.is_err());
+        let strict =
+            std::env::temp_dir().join(format!("maedic-strict-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(&strict, "application:\n  strict_config: true\n").unwrap();
+        let config = load_settings(&["tests/bad_config.yaml".into(), strict.clone()]);
+        std::fs::remove_file(&strict).unwrap();
         match config {
             Ok(_config) => panic!("this config should not be accepted as good"),
             Err(err) => assert_eq!(
                 err.to_string(),
-                "missing configuration field \"database.trust_cert\"".to_string()
+                "unknown configuration field \"database.trust_certs\"".to_string()
             ),
         }
     }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let (settings, warnings) =
+            load_settings_with_warnings(&["tests/bad_config.yaml".into()]).unwrap();
+        assert_eq!(warnings.unknown_keys, vec!["database.trust_certs"]);
+        // The misspelled key falls back to the default
+        assert!(settings.database.trust_cert);
+    }
+
+    #[test]
+    fn the_limits_file_is_relative_to_the_config_files() {
+        let directory =
+            std::env::temp_dir().join(format!("maedic-limits-file-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&directory).unwrap();
+        let base = directory.join("base.yaml");
+        std::fs::write(
+            &base,
+            "application:\n  limits_file: limits.yaml\nlimits:\n  hi_queue_count: 10\n  spool_file_count: 3\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("limits.yaml"),
+            "limits:\n  hi_queue_count: 5\n",
+        )
+        .unwrap();
+        let settings = load_settings_with_warnings(&[base]);
+        std::fs::remove_dir_all(&directory).unwrap();
+        let (settings, warnings) = settings.unwrap();
+        assert_eq!(warnings.shadowed_limits.len(), 1);
+        assert!(
+            warnings.shadowed_limits[0]
+                .starts_with("limits.hi_queue_count of the config files is overridden with 5 from")
+        );
+        assert_eq!(
+            settings.application.limits_file,
+            directory.join("limits.yaml").display().to_string()
+        );
+        assert_eq!(settings.limits.hi_queue_count, 5);
+        assert_eq!(settings.limits.spool_file_count, 3);
+    }
+
+    #[test]
+    fn missing_keys_take_their_default() {
+        let path =
+            std::env::temp_dir().join(format!("maedic-minimal-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "database:\n  hostname: SQLSERVER\n  username: maedic\n  password: Charlie 13\n",
+        )
+        .unwrap();
+        let settings = load_settings_with_warnings(std::slice::from_ref(&path));
+        std::fs::remove_file(&path).unwrap();
+        let (settings, warnings) = settings.unwrap();
+        assert_eq!(warnings, ConfigWarnings::default());
+        assert_eq!(settings.database.hostname, "SQLSERVER");
+        assert_eq!(settings.database.username, "maedic");
+        assert_eq!(settings.database.port, 1433);
+        assert_eq!(settings.limits, LimitSettings::default());
+        assert_eq!(settings.metrics, MetricSettings::default());
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn secrets_are_resolved_from_the_environment() {
+        let secret = resolve_secret("env:PATH".to_string()).unwrap();
+        assert_eq!(secret.expose_secret(), std::env::var("PATH").unwrap());
+        assert!(resolve_secret("env:MAEDIC_SURELY_MISSING_SECRET".to_string()).is_err());
+    }
+
+    #[test]
+    fn secrets_are_resolved_from_files() {
+        let path = std::env::temp_dir().join(format!("maedic-secret-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "Charlie 13\n").unwrap();
+        let secret = resolve_secret(format!("file:{}", path.display())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(secret.expose_secret(), "Charlie 13");
+        assert!(resolve_secret(format!("file:{}", path.display())).is_err());
+    }
+
+    #[test]
+    fn literal_secrets_are_kept() {
+        let secret = resolve_secret("Charlie 13".to_string()).unwrap();
+        assert_eq!(secret.expose_secret(), "Charlie 13");
+    }
+
+    #[test]
+    fn environment_overrides_the_config_file() {
+        let overrides = [
+            ("MAEDIC__DATABASE__PASSWORD", "from the environment"),
+            ("MAEDIC__DATABASE__POOL_MAX_SIZE", "7"),
+            ("MAEDIC__DATABASE__USERNAME", ""),
+            ("MAEDIC__LIMITS__CHECK_LOCAL_SERVICE", "true"),
+            ("OTHER__DATABASE__PORT", "1"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let settings: Settings = Config::builder()
+            .add_source(File::from_str(
+                include_str!("../settings/test.yaml"),
+                FileFormat::Yaml,
+            ))
+            .add_source(environment_overrides().source(Some(overrides)))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        assert_eq!(
+            settings.database.password.expose_secret(),
+            "from the environment"
+        );
+        assert_eq!(settings.database.pool_max_size, 7);
+        assert_eq!(settings.database.username, "sa");
+        assert!(settings.limits.check_local_service);
+        assert_eq!(settings.database.port, 1433);
+    }
+
+    #[test]
+    fn config_directories_are_layered() {
+        let directory =
+            std::env::temp_dir().join(format!("maedic-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&directory).unwrap();
+        std::fs::write(
+            directory.join("base.yaml"),
+            include_str!("../settings/test.yaml"),
+        )
+        .unwrap();
+        std::fs::write(directory.join("local.yaml"), "application:\n  port: 4000\n").unwrap();
+
+        let files = resolve_config_files(std::slice::from_ref(&directory)).unwrap();
+        let settings = load_settings(&files);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            files,
+            vec![directory.join("base.yaml"), directory.join("local.yaml")]
+        );
+        let settings = settings.unwrap();
+        assert_eq!(settings.application.port, 4000);
+        assert_eq!(settings.database.port, 1433);
+    }
+
+    #[test]
+    fn config_files_are_loaded_in_the_given_order() {
+        let files =
+            resolve_config_files(&["settings/base.yaml".into(), "settings/test.yaml".into()])
+                .unwrap();
+        let settings = load_settings(&files).unwrap();
+        assert!(!settings.limits.check_local_service);
+    }
+
+    #[test]
+    fn missing_config_files_are_refused() {
+        assert!(resolve_config_files(&["settings/surely_missing.yaml".into()]).is_err());
+        assert!(resolve_config_files(&["tests".into()]).is_err());
+    }
 }
@@ -1,14 +1,30 @@
-use bb8::Pool;
 use bb8_tiberius::ConnectionManager;
 use secrecy::ExposeSecret;
 use serde::{Deserialize, Serialize};
-use tiberius::{AuthMethod, Config};
-use tokio::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+use tiberius::{AuthMethod, Config, Query};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
 
 use crate::{
     configuration::{DBAuthMethod, DBConnectionPool, DatabaseSettings},
-    error::ApplicationError,
-    model::{hi_queue::HiQueueCount, panel::PanelInstalled, spoolfile::SpoolFileCount},
+    error::{ApplicationError, DatabaseSetupError},
+    failover::FailoverConnectionManager,
+    metrics::{QueryTimeouts, query_durations},
+    model::{
+        access_event::{AccessEventCount, AccessEventGrouping, AccessResult},
+        audit_log::{AuditChangeCount, AuditLogEntry, REDACTED},
+        backup::BackupHealth,
+        event_log::EventLogRetention,
+        hi_queue::HiQueueCount,
+        login_attempt::FailedLoginCount,
+        mssql::{DatabaseFile, MssqlActivity, MssqlInstance, ReplicaState, TableSize, WaitStat},
+        panel::PanelInstalled,
+        spoolfile::SpoolFileCount,
+        workstation::{ClientSessionCount, DatabaseSessionCount, WorkstationLogin},
+    },
+    run::AppState,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -18,30 +34,134 @@ pub enum DatabaseConnectionState {
 }
 
 #[tracing::instrument(name = "Setup Database connection pool", skip_all)]
-pub async fn setup_database_pool(
+pub fn setup_database_pool(
     db_config: DatabaseSettings,
-) -> Result<Pool<ConnectionManager>, tiberius::error::Error> {
-    let mut config = Config::new();
-
-    setup_auth(db_config.clone(), &mut config);
-    config.host(db_config.hostname);
-    config.port(db_config.port);
-    if db_config.trust_cert {
-        config.trust_cert();
+) -> Result<DBConnectionPool, DatabaseSetupError> {
+    if db_config.pool_max_size == 0 {
+        return Err(DatabaseSetupError::InvalidPoolSettings(
+            "pool_max_size must be greater than 0".to_string(),
+        ));
     }
-    config.database(db_config.database_name);
+    if db_config.pool_min_idle > db_config.pool_max_size {
+        return Err(DatabaseSetupError::InvalidPoolSettings(
+            "pool_min_idle must not be greater than pool_max_size".to_string(),
+        ));
+    }
+    if db_config.connection_timeout_seconds == 0 {
+        return Err(DatabaseSetupError::InvalidPoolSettings(
+            "connection_timeout_seconds must be greater than 0".to_string(),
+        ));
+    }
+    let base_config = base_connection_config(&db_config)?;
+    // The connection string replaces the hostname and port of the primary host
+    let mut hosts = match db_config.connection_string {
+        Some(_) => vec![(base_config.get_addr(), base_config.clone())],
+        None => vec![(
+            db_config.hostname.clone(),
+            host_connection_config(&base_config, &db_config.hostname, db_config.port),
+        )],
+    };
+    for partner in &db_config.failover_partners {
+        let (host, port) = parse_failover_partner(partner, db_config.port)?;
+        hosts.push((
+            partner.clone(),
+            host_connection_config(&base_config, &host, port),
+        ));
+    }
+    let (hosts, managers) = hosts
+        .into_iter()
+        .map(|(host, config)| {
+            // Resolves named instances through the SQL Browser, other hosts are connected directly
+            (
+                host,
+                ConnectionManager::new(config).using_named_connection(),
+            )
+        })
+        .unzip();
+    let mgr = FailoverConnectionManager::new(managers, hosts, db_config.multi_subnet_failover);
+    let pool = bb8::Pool::builder()
+        .max_size(db_config.pool_max_size)
+        .min_idle((db_config.pool_min_idle != 0).then_some(db_config.pool_min_idle))
+        .connection_timeout(Duration::from_secs(db_config.connection_timeout_seconds))
+        .idle_timeout(
+            (db_config.idle_timeout_seconds != 0)
+                .then(|| Duration::from_secs(db_config.idle_timeout_seconds)),
+        )
+        .max_lifetime(
+            (db_config.max_lifetime_seconds != 0)
+                .then(|| Duration::from_secs(db_config.max_lifetime_seconds)),
+        )
+        // Connections are established lazily so maedic starts without a reachable database
+        .build_unchecked(mgr);
+
+    Ok(DBConnectionPool {
+        pool,
+        query_timeout: (db_config.query_timeout_seconds != 0)
+            .then(|| Duration::from_secs(db_config.query_timeout_seconds)),
+        query_durations: query_durations(),
+        query_timeouts: QueryTimeouts::default(),
+    })
+}
+
+/// The tiberius `Config` shared by all hosts, either from the connection string or from the single settings
+fn base_connection_config(db_config: &DatabaseSettings) -> Result<Config, DatabaseSetupError> {
+    let mut config = match &db_config.connection_string {
+        Some(connection_string) => Config::from_ado_string(connection_string.expose_secret())
+            .map_err(DatabaseSetupError::InvalidConnectionString)?,
+        None => {
+            let mut config = Config::new();
+            setup_auth(db_config.clone(), &mut config)?;
+            if db_config.trust_cert {
+                config.trust_cert();
+            }
+            config.database(&db_config.database_name);
+            config
+        }
+    };
     // Connection should always be readonly as we are just monitoring
     config.readonly(true);
+    Ok(config)
+}
 
-    let tcp = TcpStream::connect(config.get_addr()).await?;
-    tcp.set_nodelay(true)?;
-    let mgr = bb8_tiberius::ConnectionManager::build(config).unwrap();
-    let pool = bb8::Pool::builder().max_size(5).build(mgr).await.unwrap();
+/// The tiberius `Config` for a single host, which can be a named instance like `SQLSERVER\PROWATCH`
+/// The port of a named instance is resolved through the SQL Browser
+fn host_connection_config(base_config: &Config, host: &str, port: u16) -> Config {
+    let mut config = base_config.clone();
+    match host.split_once('\\') {
+        Some((host, instance_name)) => {
+            config.host(host);
+            config.instance_name(instance_name);
+        }
+        None => {
+            config.host(host);
+            config.port(port);
+        }
+    }
+    config
+}
 
-    Ok(pool)
+/// Split a failover partner given as `host` or `host:port`, falling back to the configured port
+pub(crate) fn parse_failover_partner(
+    partner: &str,
+    default_port: u16,
+) -> Result<(String, u16), DatabaseSetupError> {
+    match partner.rsplit_once(':') {
+        Some((host, port)) => port
+            .parse()
+            .map(|port| (host.to_string(), port))
+            .map_err(|_| DatabaseSetupError::InvalidFailoverPartner(partner.to_string())),
+        None if partner.is_empty() => Err(DatabaseSetupError::InvalidFailoverPartner(
+            partner.to_string(),
+        )),
+        None => Ok((partner.to_string(), default_port)),
+    }
 }
+
 #[cfg(target_os = "windows")]
-pub(crate) fn setup_auth(db_config: DatabaseSettings, pool_config: &mut Config) {
+pub(crate) fn setup_auth(
+    db_config: DatabaseSettings,
+    pool_config: &mut Config,
+) -> Result<(), DatabaseSetupError> {
     match db_config.auth_method {
         DBAuthMethod::Basic => {
             pool_config.authentication(AuthMethod::sql_server(
@@ -59,10 +179,14 @@ pub(crate) fn setup_auth(db_config: DatabaseSettings, pool_config: &mut Config)
             pool_config.authentication(AuthMethod::Integrated);
         }
     }
+    Ok(())
 }
 
 #[cfg(target_os = "linux")]
-pub(crate) fn setup_auth(db_config: DatabaseSettings, pool_config: &mut Config) {
+pub(crate) fn setup_auth(
+    db_config: DatabaseSettings,
+    pool_config: &mut Config,
+) -> Result<(), DatabaseSetupError> {
     match db_config.auth_method {
         DBAuthMethod::Basic => {
             pool_config.authentication(AuthMethod::sql_server(
@@ -73,27 +197,93 @@ pub(crate) fn setup_auth(db_config: DatabaseSettings, pool_config: &mut Config)
         DBAuthMethod::Integrated => {
             pool_config.authentication(AuthMethod::Integrated);
         }
-        _ => panic!("only basic and integrated auth are supported on linux"),
+        DBAuthMethod::Windows => {
+            return Err(DatabaseSetupError::UnsupportedAuthMethod(
+                "only basic and integrated auth are supported on linux".to_string(),
+            ));
+        }
     }
+    Ok(())
 }
 
 #[tracing::instrument(name = "check database connection", skip(pool))]
 pub(crate) async fn get_db_status(
     pool: DBConnectionPool,
 ) -> Result<DatabaseConnectionState, ApplicationError> {
-    match pool
-        .get()
-        .await?
-        .simple_query("SELECT 1 as connection_state")
-        .await?
-        .into_row()
-        .await?
-        .ok_or(ApplicationError::EmptyResult)?
-        .get::<i32, &str>("connection_state")
-        .ok_or(DatabaseConnectionState::Unhealthy)
-    {
-        Ok(1) => Ok(DatabaseConnectionState::Healthy),
-        _ => Ok(DatabaseConnectionState::Unhealthy),
+    pool.timed(async {
+        match pool
+            .get()
+            .await?
+            .simple_query("SELECT 1 as connection_state")
+            .await?
+            .into_row()
+            .await?
+            .ok_or(ApplicationError::EmptyResult)?
+            .get::<i32, &str>("connection_state")
+            .ok_or(DatabaseConnectionState::Unhealthy)
+        {
+            Ok(1) => Ok(DatabaseConnectionState::Healthy),
+            _ => Ok(DatabaseConnectionState::Unhealthy),
+        }
+    })
+    .await
+}
+
+/// Check whether the database is reachable, treating any error as an unhealthy connection
+pub async fn check_database_connection(pool: DBConnectionPool) -> DatabaseConnectionState {
+    get_db_status(pool)
+        .await
+        .unwrap_or(DatabaseConnectionState::Unhealthy)
+}
+
+/// Shortest wait before retrying to reach an unreachable database
+const RECONNECT_MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Periodically check the database connection and retry with an exponential backoff while it is unreachable
+pub async fn monitor_database_connection(
+    state: Arc<Mutex<AppState>>,
+    check_interval: Duration,
+    max_backoff: Duration,
+) {
+    let mut previous_state = None;
+    let mut backoff = RECONNECT_MIN_BACKOFF;
+    loop {
+        // The lock is not held during the check so requests are not blocked by a slow connection
+        let pool = state.lock().await.pool.clone();
+        let connection_state = check_database_connection(pool).await;
+        let wait = {
+            let mut state = state.lock().await;
+            state
+                .metrics
+                .set_database_up(connection_state == DatabaseConnectionState::Healthy);
+            match (&previous_state, &connection_state) {
+                (Some(DatabaseConnectionState::Unhealthy), DatabaseConnectionState::Unhealthy) => {
+                    state.metrics.inc_database_reconnect_attempts();
+                }
+                (_, DatabaseConnectionState::Unhealthy) => {
+                    warn!("Database is unreachable, retrying with backoff");
+                    state.metrics.inc_database_outages();
+                }
+                (Some(DatabaseConnectionState::Unhealthy), DatabaseConnectionState::Healthy) => {
+                    info!("Database connection reestablished");
+                }
+                _ => {}
+            }
+            state.database_connection = connection_state.clone();
+            match connection_state {
+                DatabaseConnectionState::Healthy => {
+                    backoff = RECONNECT_MIN_BACKOFF;
+                    check_interval
+                }
+                DatabaseConnectionState::Unhealthy => {
+                    let wait = backoff;
+                    backoff = (backoff * 2).min(max_backoff);
+                    wait
+                }
+            }
+        };
+        previous_state = Some(connection_state);
+        tokio::time::sleep(wait).await;
     }
 }
 
@@ -102,15 +292,32 @@ pub(crate) async fn get_unhealthy_spoolfiles(
     pool: DBConnectionPool,
     limit_per_channel: i32,
 ) -> Result<Vec<SpoolFileCount>, ApplicationError> {
-    let mut client = pool.get().await?;
-    let queryresult = client
-        .query("select DESCRP as description, SPOOl_FILE_COUNT as spool_file_count, SPOOL_DIR as directory from CHANNEL where Installed = 'Y' and SPOOl_FILE_COUNT > @P1", &[&limit_per_channel])
-        .await?.into_results().await?;
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        // One row per panel on the channel, they are merged into the panels of the channel below
+        let queryresult = client
+            .query("select CHANNEL.DESCRP as description, CHANNEL.SPOOl_FILE_COUNT as spool_file_count, CHANNEL.SPOOL_DIR as directory, PANEL.DESCRP as panel
+                from CHANNEL left join PANEL on PANEL.CHANNEL = CHANNEL.ID
+                where CHANNEL.Installed = 'Y' and CHANNEL.SPOOl_FILE_COUNT > @P1
+                order by CHANNEL.ID", &[&limit_per_channel])
+            .await?.into_results().await?;
 
-    let spool_file_counts: Vec<SpoolFileCount> =
-        queryresult[0].iter().map(|row| row.into()).collect();
+        let mut spool_file_counts: Vec<SpoolFileCount> = Vec::new();
+        for spool_file_count in queryresult[0].iter().map(SpoolFileCount::from) {
+            match spool_file_counts.last_mut() {
+                Some(channel)
+                    if channel.description == spool_file_count.description
+                        && channel.directory == spool_file_count.directory =>
+                {
+                    channel.panels.extend(spool_file_count.panels)
+                }
+                _ => spool_file_counts.push(spool_file_count),
+            }
+        }
 
-    Ok(spool_file_counts)
+        Ok(spool_file_counts)
+    })
+    .await
 }
 
 #[tracing::instrument(name = "Check Table Size", skip(pool))]
@@ -118,18 +325,21 @@ pub(crate) async fn get_table_count(
     pool: DBConnectionPool,
     tablename: String,
 ) -> Result<i32, ApplicationError> {
-    let mut client = pool.get().await?;
-    let size = client
-        .simple_query(format!("SELECT COUNT(*) as COUNT FROM {}", tablename))
-        .await?
-        .into_row()
-        .await?
-        .ok_or(ApplicationError::EmptyResult)?
-        .get::<i32, &str>("COUNT")
-        .ok_or(ApplicationError::Conversion(
-            "Failed to convert COUNT".to_string(),
-        ))?;
-    Ok(size)
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let size = client
+            .simple_query(format!("SELECT COUNT(*) as COUNT FROM {}", tablename))
+            .await?
+            .into_row()
+            .await?
+            .ok_or(ApplicationError::EmptyResult)?
+            .get::<i32, &str>("COUNT")
+            .ok_or(ApplicationError::Conversion(
+                "Failed to convert COUNT".to_string(),
+            ))?;
+        Ok(size)
+    })
+    .await
 }
 
 #[tracing::instrument(name = "Check Card Status", skip(pool))]
@@ -137,164 +347,808 @@ pub(crate) async fn get_card_state(
     pool: DBConnectionPool,
     status: String,
 ) -> Result<i32, ApplicationError> {
-    let mut client = pool.get().await?;
-    let size = client
-        .simple_query(format!(
-            "SELECT COUNT(*) as COUNT FROM Badge_C where STAT_COD = '{}'",
-            status
-        ))
-        .await?
-        .into_row()
-        .await?
-        .ok_or(ApplicationError::EmptyResult)?
-        .get::<i32, &str>("COUNT")
-        .ok_or(ApplicationError::Conversion(
-            "Failed to convert COUNT".to_string(),
-        ))?;
-    Ok(size)
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let size = client
+            .simple_query(format!(
+                "SELECT COUNT(*) as COUNT FROM Badge_C where STAT_COD = '{}'",
+                status
+            ))
+            .await?
+            .into_row()
+            .await?
+            .ok_or(ApplicationError::EmptyResult)?
+            .get::<i32, &str>("COUNT")
+            .ok_or(ApplicationError::Conversion(
+                "Failed to convert COUNT".to_string(),
+            ))?;
+        Ok(size)
+    })
+    .await
 }
 
 #[tracing::instrument(name = "Get Version & build number", skip(pool))]
 pub(crate) async fn get_version_number(
     pool: DBConnectionPool,
 ) -> Result<(u8, u8, u8, i32), ApplicationError> {
-    let mut client = pool.get().await?;
-    let tablesize = client
-        .simple_query("SELECT COUNT(*) as COUNT FROM db_version")
-        .await?
-        .into_row()
-        .await?
-        .ok_or(ApplicationError::EmptyResult)?
-        .get::<i32, &str>("COUNT")
-        .ok_or(ApplicationError::Conversion(
-            "Failed to convert COUNT".to_string(),
-        ))?;
-    if tablesize != 0 {
-        let result = client
-            .simple_query(
-                "SELECT VER_MAJOR_NUM, VER_MINOR_NUM, VER_SP_NUM, Build_No FROM db_version",
-            )
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let tablesize = client
+            .simple_query("SELECT COUNT(*) as COUNT FROM db_version")
             .await?
             .into_row()
             .await?
-            .ok_or(ApplicationError::EmptyResult)?;
-
-        let major = result
-            .get::<u8, &str>("VER_MAJOR_NUM")
-            .ok_or(ApplicationError::Conversion(
-                "Failed to convert Version".to_string(),
-            ))?;
-        let minor = result
-            .get::<u8, &str>("VER_MINOR_NUM")
-            .ok_or(ApplicationError::Conversion(
-                "Failed to convert Build_no".to_string(),
-            ))?;
-        let patch = result
-            .get::<u8, &str>("VER_SP_NUM")
-            .ok_or(ApplicationError::Conversion(
-                "Failed to convert Version".to_string(),
-            ))?;
-        let build_no = result
-            .get::<i32, &str>("Build_No")
+            .ok_or(ApplicationError::EmptyResult)?
+            .get::<i32, &str>("COUNT")
             .ok_or(ApplicationError::Conversion(
-                "Failed to convert Build_no".to_string(),
+                "Failed to convert COUNT".to_string(),
             ))?;
-        return Ok((major, minor, patch, build_no));
-    }
-    Ok((0, 0, 0, 0))
+        if tablesize != 0 {
+            let result = client
+                .simple_query(
+                    "SELECT VER_MAJOR_NUM, VER_MINOR_NUM, VER_SP_NUM, Build_No FROM db_version",
+                )
+                .await?
+                .into_row()
+                .await?
+                .ok_or(ApplicationError::EmptyResult)?;
+
+            let major =
+                result
+                    .get::<u8, &str>("VER_MAJOR_NUM")
+                    .ok_or(ApplicationError::Conversion(
+                        "Failed to convert Version".to_string(),
+                    ))?;
+            let minor =
+                result
+                    .get::<u8, &str>("VER_MINOR_NUM")
+                    .ok_or(ApplicationError::Conversion(
+                        "Failed to convert Build_no".to_string(),
+                    ))?;
+            let patch =
+                result
+                    .get::<u8, &str>("VER_SP_NUM")
+                    .ok_or(ApplicationError::Conversion(
+                        "Failed to convert Version".to_string(),
+                    ))?;
+            let build_no =
+                result
+                    .get::<i32, &str>("Build_No")
+                    .ok_or(ApplicationError::Conversion(
+                        "Failed to convert Build_no".to_string(),
+                    ))?;
+            return Ok((major, minor, patch, build_no));
+        }
+        Ok((0, 0, 0, 0))
+    })
+    .await
 }
 
 #[tracing::instrument(name = "Get Hi_Queue per Panel", skip(pool))]
 pub(crate) async fn get_hiqueue_count_per_panel(
     pool: DBConnectionPool,
 ) -> Result<Vec<HiQueueCount>, ApplicationError> {
-    let mut client = pool.get().await?;
-    let panel_tablesize = match client
-        .simple_query("SELECT COUNT(*) as COUNT FROM Panel")
-        .await?
-        .into_row()
-        .await?
-    {
-        Some(result) => result
-            .get::<i32, &str>("COUNT")
-            .ok_or(ApplicationError::Conversion(
-                "Failed to convert COUNT".to_string(),
-            ))?,
-        None => {
-            return Err(ApplicationError::Conversion(
-                "No result received".to_string(),
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let panel_tablesize = match client
+            .simple_query("SELECT COUNT(*) as COUNT FROM Panel")
+            .await?
+            .into_row()
+            .await?
+        {
+            Some(result) => result
+                .get::<i32, &str>("COUNT")
+                .ok_or(ApplicationError::Conversion(
+                    "Failed to convert COUNT".to_string(),
+                ))?,
+            None => {
+                return Err(ApplicationError::Conversion(
+                    "No result received".to_string(),
+                ));
+            }
+        };
+        let hi_queue_tablesize = match client
+            .simple_query("SELECT COUNT(*) as COUNT FROM HI_QUEUE")
+            .await?
+            .into_row()
+            .await?
+        {
+            Some(result) => result
+                .get::<i32, &str>("COUNT")
+                .ok_or(ApplicationError::Conversion(
+                    "Failed to convert COUNT".to_string(),
+                ))?,
+            None => {
+                return Err(ApplicationError::Conversion(
+                    "No result received".to_string(),
+                ));
+            }
+        };
+        if panel_tablesize != 0 && hi_queue_tablesize != 0 {
+            let result = client
+                .simple_query(
+                "select DESCRP as 'description', COUNT(*) as 'hi_queue_count' from (
+    	            select Panel.DESCRP, HI_QUEUE.ID from HI_QUEUE inner join Panel on HI_QUEUE.CPAR2 = Panel.ID
+    	            union all
+    	            select Panel.Descrp, HI_QUEUE.ID from HI_QUEUE inner join Panel on LEFT(HI_QUEUE.CPAR1,(CHARINDEX(':', CPAR1) + 7)) = PANEL.ID
+
+                ) as interims
+                group by DESCRP"
+                )
+                .await?
+                .into_results()
+                .await?;
+            let hi_queue_count: Vec<HiQueueCount> = result[0].iter().map(|row| row.into()).collect();
+            return Ok(hi_queue_count);
+        }
+        Ok(Vec::new())
+    })
+    .await
+}
+
+#[tracing::instrument(name = "Get Firmware Records", skip(pool))]
+pub(crate) async fn get_panel_state(
+    pool: DBConnectionPool,
+) -> Result<Vec<PanelInstalled>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let panel_tablesize = match client
+            .simple_query("SELECT COUNT(*) as COUNT FROM Panel")
+            .await?
+            .into_row()
+            .await?
+        {
+            Some(result) => result
+                .get::<i32, &str>("COUNT")
+                .ok_or(ApplicationError::Conversion(
+                    "Failed to convert COUNT".to_string(),
+                ))?,
+            None => {
+                return Err(ApplicationError::Conversion(
+                    "No result received".to_string(),
+                ));
+            }
+        };
+        if panel_tablesize != 0 {
+            let result = client
+                .simple_query(
+                    "select DESCRP as 'description', FIRMWARE_VERSION as 'firmware_version', INSTALLED as 'installed' from Panel",
+                )
+                .await?
+                .into_results()
+                .await?;
+            let hi_queue_count: Vec<PanelInstalled> = result[0].iter().map(|row| row.into()).collect();
+            return Ok(hi_queue_count);
+        }
+        Ok(Vec::new())
+    })
+    .await
+}
+
+/// Comma separated query parameters `@P<first>` and onwards, one for each value
+fn parameter_list(first: usize, count: usize) -> String {
+    (first..first + count)
+        .map(|index| format!("@P{index}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Count the access events in `EV_LOG` of the last `window_minutes` grouped by device or panel
+/// Each event lands in the first matching result of the `event_types`, without any event types the English
+/// `EVNT_DESCRP` is matched instead
+/// Events are classified by their description, e.g. "Local Grant", "Access Denied" or "Invalid Card"
+#[tracing::instrument(name = "Get Access Events", skip(pool))]
+pub(crate) async fn get_access_events(
+    pool: DBConnectionPool,
+    grouping: AccessEventGrouping,
+    window_minutes: i32,
+    event_types: &[(AccessResult, &[String])],
+) -> Result<Vec<AccessEventCount>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let configured: Vec<&(AccessResult, &[String])> = event_types
+            .iter()
+            .filter(|(_, types)| !types.is_empty())
+            .collect();
+        let mut cases = Vec::new();
+        let mut next_parameter = 2;
+        for (result, types) in &configured {
+            cases.push(format!(
+                "WHEN EVNT_TYPE in ({}) THEN '{}'",
+                parameter_list(next_parameter, types.len()),
+                result.column()
             ));
+            next_parameter += types.len();
         }
-    };
-    let hi_queue_tablesize = match client
-        .simple_query("SELECT COUNT(*) as COUNT FROM HI_QUEUE")
-        .await?
-        .into_row()
-        .await?
-    {
-        Some(result) => result
+        if cases.is_empty() {
+            cases = vec![
+                "WHEN EVNT_DESCRP like '%Invalid%' THEN 'invalid_card'".to_string(),
+                "WHEN EVNT_DESCRP like '%Denied%' or EVNT_DESCRP like '%Violation%' THEN 'denied'"
+                    .to_string(),
+                "WHEN EVNT_DESCRP like '%Grant%' THEN 'granted'".to_string(),
+            ];
+        }
+        let mut query = Query::new(format!(
+            "select description,
+                SUM(CASE WHEN result = 'granted' THEN 1 ELSE 0 END) as 'granted',
+                SUM(CASE WHEN result = 'denied' THEN 1 ELSE 0 END) as 'denied',
+                SUM(CASE WHEN result = 'invalid_card' THEN 1 ELSE 0 END) as 'invalid_card'
+            from (
+                select {grouping} as description, CASE {} END as result
+                from EV_LOG
+                where EVNT_DAT >= DATEADD(minute, -@P1, GETDATE()) and {grouping} is not null
+            ) as events
+            group by description",
+            cases.join(" ")
+        ));
+        query.bind(window_minutes);
+        for (_, types) in configured {
+            for event_type in types.iter() {
+                query.bind(event_type.as_str());
+            }
+        }
+        let result = query.query(&mut client).await?.into_results().await?;
+        let access_events: Vec<AccessEventCount> = result[0].iter().map(|row| row.into()).collect();
+        Ok(access_events)
+    })
+    .await
+}
+
+#[tracing::instrument(name = "Get PW client sessions", skip(pool))]
+pub(crate) async fn get_client_sessions(
+    pool: DBConnectionPool,
+    stale_minutes: i32,
+) -> Result<Vec<ClientSessionCount>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let result = client
+            .query(
+                "select WRKST as 'workstation',
+                    SUM(CASE WHEN LAST_UPDATE >= DATEADD(minute, -@P1, GETDATE()) THEN 1 ELSE 0 END) as 'active',
+                    SUM(CASE WHEN LAST_UPDATE >= DATEADD(minute, -@P1, GETDATE()) THEN 0 ELSE 1 END) as 'stale'
+                from SESSION
+                where WRKST is not null
+                group by WRKST",
+                &[&stale_minutes],
+            )
+            .await?
+            .into_results()
+            .await?;
+        let sessions: Vec<ClientSessionCount> = result[0].iter().map(|row| row.into()).collect();
+        Ok(sessions)
+    })
+    .await
+}
+
+#[tracing::instrument(name = "Count connected workstations", skip(pool))]
+pub(crate) async fn get_connected_workstation_count(
+    pool: DBConnectionPool,
+    stale_minutes: i32,
+    workstations: &[String],
+) -> Result<i32, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        // Without workstations every session counts, otherwise only those of the listed workstations
+        let filter = if workstations.is_empty() {
+            String::new()
+        } else {
+            format!(
+                " and WRKST in ({})",
+                parameter_list(2, workstations.len())
+            )
+        };
+        let mut query = Query::new(format!(
+            "select COUNT(DISTINCT WRKST) as COUNT from SESSION where LAST_UPDATE >= DATEADD(minute, -@P1, GETDATE()){filter}"
+        ));
+        query.bind(stale_minutes);
+        for workstation in workstations {
+            query.bind(workstation.as_str());
+        }
+        let count = query
+            .query(&mut client)
+            .await?
+            .into_row()
+            .await?
+            .ok_or(ApplicationError::EmptyResult)?
             .get::<i32, &str>("COUNT")
             .ok_or(ApplicationError::Conversion(
                 "Failed to convert COUNT".to_string(),
-            ))?,
-        None => {
-            return Err(ApplicationError::Conversion(
-                "No result received".to_string(),
-            ));
-        }
-    };
-    if panel_tablesize != 0 && hi_queue_tablesize != 0 {
+            ))?;
+        Ok(count)
+    })
+    .await
+}
+
+#[tracing::instrument(name = "Get Workstation logins", skip(pool))]
+pub(crate) async fn get_workstation_logins(
+    pool: DBConnectionPool,
+) -> Result<Vec<WorkstationLogin>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
         let result = client
             .simple_query(
-            "select DESCRP as 'description', COUNT(*) as 'hi_queue_count' from (
-	            select Panel.DESCRP, HI_QUEUE.ID from HI_QUEUE inner join Panel on HI_QUEUE.CPAR2 = Panel.ID
-	            union all
-	            select Panel.Descrp, HI_QUEUE.ID from HI_QUEUE inner join Panel on LEFT(HI_QUEUE.CPAR1,(CHARINDEX(':', CPAR1) + 7)) = PANEL.ID
+                "select WRKST_NAME as 'workstation', DATEDIFF(day, TRY_CONVERT(datetime, LASTLOGINTIMESTAMP), GETDATE()) as 'days_since_login' from WRKST",
+            )
+            .await?
+            .into_results()
+            .await?;
+        let logins: Vec<WorkstationLogin> = result[0].iter().map(|row| row.into()).collect();
+        Ok(logins)
+    })
+    .await
+}
 
-            ) as interims
-            group by DESCRP"
+/// Connections of all clients to the SQL Server, requires `VIEW SERVER STATE` to see other sessions than maedics own
+#[tracing::instrument(name = "Get Database sessions", skip(pool))]
+pub(crate) async fn get_database_sessions(
+    pool: DBConnectionPool,
+) -> Result<Vec<DatabaseSessionCount>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let result = client
+            .simple_query(
+                "select ISNULL(host_name, '') as 'host_name', ISNULL(program_name, '') as 'program_name', COUNT(*) as 'session_count'
+                from sys.dm_exec_sessions
+                where is_user_process = 1
+                group by host_name, program_name",
             )
             .await?
             .into_results()
             .await?;
-        let hi_queue_count: Vec<HiQueueCount> = result[0].iter().map(|row| row.into()).collect();
-        return Ok(hi_queue_count);
-    }
-    Ok(Vec::new())
+        let sessions: Vec<DatabaseSessionCount> = result[0].iter().map(|row| row.into()).collect();
+        Ok(sessions)
+    })
+    .await
 }
 
-#[tracing::instrument(name = "Get Firmware Records", skip(pool))]
-pub(crate) async fn get_panel_state(
+#[tracing::instrument(name = "Get failed operator logins", skip(pool))]
+pub(crate) async fn get_failed_logins(
     pool: DBConnectionPool,
-) -> Result<Vec<PanelInstalled>, ApplicationError> {
-    let mut client = pool.get().await?;
-    let panel_tablesize = match client
-        .simple_query("SELECT COUNT(*) as COUNT FROM Panel")
-        .await?
-        .into_row()
-        .await?
-    {
-        Some(result) => result
+    window_minutes: i32,
+) -> Result<Vec<FailedLoginCount>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let result = client
+            .query(
+                "select ISNULL(UID.USERNAME, CONVERT(nvarchar(40), UID_LOGIN_ATTEMPTS.USERID, 2)) as 'username', CAST(NUM_ATTEMPTS as int) as 'failed_attempts'
+                from UID_LOGIN_ATTEMPTS left join UID on UID.ID = UID_LOGIN_ATTEMPTS.USERID
+                where NUM_ATTEMPTS > 0 and LAST_ATTEMPT >= DATEADD(minute, -@P1, GETDATE())",
+                &[&window_minutes],
+            )
+            .await?
+            .into_results()
+            .await?;
+        let failed_logins: Vec<FailedLoginCount> = result[0].iter().map(|row| row.into()).collect();
+        Ok(failed_logins)
+    })
+    .await
+}
+
+/// PW marks an operator account as locked out with `STATUS = 1` in `UID_LOGIN_ATTEMPTS`
+#[tracing::instrument(name = "Count locked operator accounts", skip(pool))]
+pub(crate) async fn get_locked_operator_count(
+    pool: DBConnectionPool,
+) -> Result<i32, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let count = client
+            .simple_query("SELECT COUNT(*) as COUNT FROM UID_LOGIN_ATTEMPTS where STATUS = 1")
+            .await?
+            .into_row()
+            .await?
+            .ok_or(ApplicationError::EmptyResult)?
             .get::<i32, &str>("COUNT")
             .ok_or(ApplicationError::Conversion(
                 "Failed to convert COUNT".to_string(),
-            ))?,
-        None => {
-            return Err(ApplicationError::Conversion(
-                "No result received".to_string(),
-            ));
+            ))?;
+        Ok(count)
+    })
+    .await
+}
+
+#[tracing::instrument(name = "Get Audit Log changes", skip(pool))]
+pub(crate) async fn get_audit_changes(
+    pool: DBConnectionPool,
+    window_minutes: i32,
+) -> Result<Vec<AuditChangeCount>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let result = client
+            .query(
+                "select TABLE_NAME as 'table_name', CAST(ISNULL(OPERATION, -1) as int) as 'operation', ISNULL(USERID, '') as 'user_id', COUNT(DISTINCT BATCH_ID) as 'change_count'
+                from AUDIT_LOG
+                where DT >= DATEADD(minute, -@P1, GETDATE())
+                group by TABLE_NAME, OPERATION, USERID",
+                &[&window_minutes],
+            )
+            .await?
+            .into_results()
+            .await?;
+        let changes: Vec<AuditChangeCount> = result[0].iter().map(|row| row.into()).collect();
+        Ok(changes)
+    })
+    .await
+}
+
+/// Every changed column of the latest `changes`, each change is one `BATCH_ID` of the `AUDIT_LOG`
+#[tracing::instrument(name = "Get recent Audit Log entries", skip(pool))]
+pub(crate) async fn get_recent_audit_log_entries(
+    pool: DBConnectionPool,
+    changes: i32,
+    redact_images: bool,
+) -> Result<Vec<AuditLogEntry>, ApplicationError> {
+    pool.timed(async {
+        let (before_image, after_image) = if redact_images {
+            (format!("'{REDACTED}'"), format!("'{REDACTED}'"))
+        } else {
+            (
+                "CONVERT(nvarchar(4000), BEFORE_IMG)".to_string(),
+                "CONVERT(nvarchar(4000), AFTER_IMG)".to_string(),
+            )
+        };
+        let mut client = pool.get().await?;
+        let result = client
+            .query(
+                format!(
+                    "select CONVERT(nvarchar(30), DT, 126) as 'timestamp', TABLE_NAME as 'table_name', COLUMN_NAME as 'column_name',
+                        CONVERT(nvarchar(4000), KEY1) as 'key', CAST(OPERATION as int) as 'operation', USERID as 'user_id', WRKST as 'workstation',
+                        {before_image} as 'before_image', {after_image} as 'after_image'
+                    from AUDIT_LOG
+                    where BATCH_ID in (
+                        select TOP (@P1) BATCH_ID from AUDIT_LOG group by BATCH_ID order by MAX(DT) desc
+                    )
+                    order by DT desc, BATCH_ID"
+                ),
+                &[&changes],
+            )
+            .await?
+            .into_results()
+            .await?;
+        let audit_log_entries: Vec<AuditLogEntry> = result[0].iter().map(|row| row.into()).collect();
+        Ok(audit_log_entries)
+    })
+    .await
+}
+
+/// Both `EV_LOG` lookups seek the index on `EVNT_DAT` instead of scanning the whole table
+#[tracing::instrument(name = "Get Event Log retention", skip(pool))]
+pub(crate) async fn get_event_log_retention(
+    pool: DBConnectionPool,
+) -> Result<EventLogRetention, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let retention = client
+            .simple_query(
+                "select
+                    (select TOP 1 DATEDIFF(day, EVNT_DAT, GETDATE()) from EV_LOG order by EVNT_DAT) as 'oldest_event_age_days',
+                    (select DATEDIFF(day, MAX(ARCH_ACTUAL_FINISH_TIME), GETDATE()) from ARCH_TRACKING) as 'days_since_last_archive',
+                    (select TOP 1 CAST(ISNULL(ARCH_FAIL, 0) as int) from ARCH_TRACKING order by ARCH_ACTUAL_START_TIME desc) as 'last_archive_failed'",
+            )
+            .await?
+            .into_row()
+            .await?
+            .ok_or(ApplicationError::EmptyResult)?;
+        Ok((&retention).into())
+    })
+    .await
+}
+
+/// Events within the last 24 hours, only exported as a metric as it counts a whole day of `EV_LOG`
+#[tracing::instrument(name = "Get recent Event count", skip(pool))]
+pub(crate) async fn get_recent_event_count(
+    pool: DBConnectionPool,
+) -> Result<i32, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let row = client
+            .simple_query(
+                "select COUNT(*) as 'events_last_day' from EV_LOG where EVNT_DAT >= DATEADD(day, -1, GETDATE())",
+            )
+            .await?
+            .into_row()
+            .await?
+            .ok_or(ApplicationError::EmptyResult)?;
+        Ok(row.get("events_last_day").unwrap_or_default())
+    })
+    .await
+}
+
+#[tracing::instrument(name = "Get SQL Server instance", skip(pool))]
+pub(crate) async fn get_mssql_instance(
+    pool: DBConnectionPool,
+) -> Result<MssqlInstance, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let instance = client
+            .simple_query(
+                "select CAST(SERVERPROPERTY('ProductVersion') as nvarchar(128)) as 'version',
+                    CAST(SERVERPROPERTY('Edition') as nvarchar(128)) as 'edition',
+                    (select DATEDIFF(second, sqlserver_start_time, GETDATE()) from sys.dm_os_sys_info) as 'uptime_seconds',
+                    (select state_desc from sys.databases where name = DB_NAME()) as 'database_state'",
+            )
+            .await?
+            .into_row()
+            .await?
+            .ok_or(ApplicationError::EmptyResult)?;
+        Ok((&instance).into())
+    })
+    .await
+}
+
+/// Size, usage, maximum size and free disk space of the data and log files in megabytes
+#[tracing::instrument(name = "Get Database files", skip(pool))]
+pub(crate) async fn get_database_files(
+    pool: DBConnectionPool,
+) -> Result<Vec<DatabaseFile>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let result = client
+            .simple_query(
+                "select name as 'name', type_desc as 'file_type', CAST(size / 128 as int) as 'size_megabytes',
+                    CAST(FILEPROPERTY(name, 'SpaceUsed') / 128 as int) as 'used_megabytes',
+                    CASE WHEN max_size = -1 THEN NULL WHEN max_size = 0 THEN CAST(size / 128 as int) ELSE CAST(max_size / 128 as int) END as 'max_size_megabytes',
+                    CAST(volumes.available_bytes / 1048576 as int) as 'volume_available_megabytes'
+                from sys.database_files
+                cross apply sys.dm_os_volume_stats(DB_ID(), file_id) as volumes",
+            )
+            .await?
+            .into_results()
+            .await?;
+        let files: Vec<DatabaseFile> = result[0].iter().map(|row| row.into()).collect();
+        Ok(files)
+    })
+    .await
+}
+
+#[tracing::instrument(name = "Get SQL Server activity", skip(pool))]
+pub(crate) async fn get_mssql_activity(
+    pool: DBConnectionPool,
+    long_running_request_seconds: i32,
+) -> Result<MssqlActivity, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let activity = client
+            .query(
+                "select
+                    (select COUNT(*) from sys.dm_exec_requests where blocking_session_id <> 0) as 'blocked_sessions',
+                    (select COUNT(*) from sys.dm_exec_requests requests
+                        inner join sys.dm_exec_sessions sessions on sessions.session_id = requests.session_id
+                        where sessions.is_user_process = 1 and requests.session_id <> @@SPID
+                        and requests.total_elapsed_time > @P1 * 1000) as 'long_running_requests'",
+                &[&long_running_request_seconds],
+            )
+            .await?
+            .into_row()
+            .await?
+            .ok_or(ApplicationError::EmptyResult)?;
+        Ok((&activity).into())
+    })
+    .await
+}
+
+/// Wait types with the highest accumulated wait time, ignoring the ones which are idle by design
+#[tracing::instrument(name = "Get SQL Server wait stats", skip(pool))]
+pub(crate) async fn get_wait_stats(
+    pool: DBConnectionPool,
+    top_n: i32,
+) -> Result<Vec<WaitStat>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let result = client
+            .query(
+                "select TOP (@P1) wait_type as 'wait_type', wait_time_ms as 'wait_time_milliseconds'
+                from sys.dm_os_wait_stats
+                where waiting_tasks_count > 0
+                    and wait_type not like '%SLEEP%'
+                    and wait_type not like '%IDLE%'
+                    and wait_type not like '%QUEUE%'
+                    and wait_type not in ('BROKER_TASK_STOP', 'BROKER_TO_FLUSH', 'CLR_AUTO_EVENT', 'CLR_MANUAL_EVENT',
+                        'DIRTY_PAGE_POLL', 'HADR_FILESTREAM_IOMGR_IOCOMPLETION', 'REQUEST_FOR_DEADLOCK_SEARCH',
+                        'SOS_WORK_DISPATCHER', 'SP_SERVER_DIAGNOSTICS_SLEEP', 'WAITFOR', 'XE_DISPATCHER_WAIT',
+                        'XE_TIMER_EVENT', 'PWAIT_EXTENSIBILITY_CLEANUP_TASK')
+                order by wait_time_ms desc",
+                &[&top_n],
+            )
+            .await?
+            .into_results()
+            .await?;
+        let wait_stats: Vec<WaitStat> = result[0].iter().map(|row| row.into()).collect();
+        Ok(wait_stats)
+    })
+    .await
+}
+
+/// Hours since the last backups of the connected database (`DB_NAME()`) from the backup history in `msdb`
+/// Missing permissions on `msdb` are reported as `BackupState::PermissionDenied` instead of an error
+#[tracing::instrument(name = "Get Backup ages", skip(pool))]
+pub(crate) async fn get_backup_health(
+    pool: DBConnectionPool,
+) -> Result<BackupHealth, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let result = client
+            .simple_query(
+                "select
+                    (select DATEDIFF(hour, MAX(backup_finish_date), GETDATE()) from msdb.dbo.backupset where database_name = DB_NAME() and type = 'D') as 'full_backup_age_hours',
+                    (select DATEDIFF(hour, MAX(backup_finish_date), GETDATE()) from msdb.dbo.backupset where database_name = DB_NAME() and type = 'I') as 'differential_backup_age_hours',
+                    (select DATEDIFF(hour, MAX(backup_finish_date), GETDATE()) from msdb.dbo.backupset where database_name = DB_NAME() and type = 'L') as 'log_backup_age_hours'",
+            )
+            .await;
+        let row = match result {
+            Ok(stream) => stream.into_row().await,
+            Err(err) => Err(err),
+        };
+        match row {
+            Ok(row) => Ok((&row.ok_or(ApplicationError::EmptyResult)?).into()),
+            // 229: permission denied on the object, 916: no access to the database
+            Err(tiberius::error::Error::Server(err)) if err.code() == 229 || err.code() == 916 => {
+                Ok(BackupHealth::permission_denied())
+            }
+            Err(err) => Err(err.into()),
         }
-    };
-    if panel_tablesize != 0 {
+    })
+    .await
+}
+
+/// Reserved space of the tables which usually make up most of the PW database growth
+#[tracing::instrument(name = "Get big Table sizes", skip(pool))]
+pub(crate) async fn get_table_sizes(
+    pool: DBConnectionPool,
+) -> Result<Vec<TableSize>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
+        let result = client
+            .simple_query(
+                "select UPPER(OBJECT_NAME(object_id)) as 'table_name', CAST(SUM(reserved_page_count) / 128 as int) as 'size_megabytes'
+                from sys.dm_db_partition_stats
+                where object_id in (OBJECT_ID('EV_LOG'), OBJECT_ID('AUDIT_LOG'), OBJECT_ID('ACCESS_HIST'))
+                group by object_id",
+            )
+            .await?
+            .into_results()
+            .await?;
+        let table_sizes: Vec<TableSize> = result[0].iter().map(|row| row.into()).collect();
+        Ok(table_sizes)
+    })
+    .await
+}
+
+/// Role and synchronization state of the availability group replicas of the PW database
+/// Empty if the database is not part of an availability group
+#[tracing::instrument(name = "Get Replica states", skip(pool))]
+pub(crate) async fn get_replica_states(
+    pool: DBConnectionPool,
+) -> Result<Vec<ReplicaState>, ApplicationError> {
+    pool.timed(async {
+        let mut client = pool.get().await?;
         let result = client
             .simple_query(
-                "select DESCRP as 'description', FIRMWARE_VERSION as 'firmware_version', INSTALLED as 'installed' from Panel",
+                "select replicas.replica_server_name as 'replica', ISNULL(replica_states.role_desc, 'UNKNOWN') as 'role',
+                    replicas.availability_mode_desc as 'availability_mode', database_states.synchronization_state_desc as 'synchronization_state'
+                from sys.dm_hadr_database_replica_states as database_states
+                join sys.availability_replicas as replicas on replicas.replica_id = database_states.replica_id
+                left join sys.dm_hadr_availability_replica_states as replica_states on replica_states.replica_id = database_states.replica_id
+                where database_states.database_id = DB_ID()",
             )
             .await?
             .into_results()
             .await?;
-        let hi_queue_count: Vec<PanelInstalled> = result[0].iter().map(|row| row.into()).collect();
-        return Ok(hi_queue_count);
+        let replica_states: Vec<ReplicaState> = result[0].iter().map(|row| row.into()).collect();
+        Ok(replica_states)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        configuration::DatabaseSettings,
+        database::{
+            host_connection_config, parameter_list, parse_failover_partner, setup_database_pool,
+        },
+        error::{ApplicationError, DatabaseSetupError},
+        metrics::QueryLabel,
+    };
+    use prometheus_client::{encoding::text::encode, registry::Registry};
+    use rstest::rstest;
+    use std::time::Duration;
+    use tiberius::Config;
+    use tracing::info_span;
+
+    #[rstest]
+    #[case(DatabaseSettings { pool_max_size: 0, ..Default::default() })]
+    #[case(DatabaseSettings { pool_min_idle: 6, ..Default::default() })]
+    #[case(DatabaseSettings { connection_timeout_seconds: 0, ..Default::default() })]
+    fn invalid_pool_settings_are_refused(#[case] db_config: DatabaseSettings) {
+        assert!(matches!(
+            setup_database_pool(db_config),
+            Err(DatabaseSetupError::InvalidPoolSettings(_))
+        ));
+    }
+
+    #[rstest]
+    #[case("mirror", ("mirror".to_string(), 1433))]
+    #[case("mirror:1444", ("mirror".to_string(), 1444))]
+    #[case("10.0.0.2:1433", ("10.0.0.2".to_string(), 1433))]
+    fn failover_partners_are_parsed(#[case] partner: &str, #[case] expected: (String, u16)) {
+        assert_eq!(parse_failover_partner(partner, 1433).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("mirror:")]
+    #[case("mirror:port")]
+    fn invalid_failover_partners_are_refused(#[case] partner: &str) {
+        assert!(matches!(
+            parse_failover_partner(partner, 1433),
+            Err(DatabaseSetupError::InvalidFailoverPartner(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(2, 1, "@P2")]
+    #[case(2, 3, "@P2, @P3, @P4")]
+    #[case(5, 0, "")]
+    fn query_parameters_are_numbered_from_the_first(
+        #[case] first: usize,
+        #[case] count: usize,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(parameter_list(first, count), expected);
+    }
+
+    #[rstest]
+    #[case("SQLSERVER", "SQLSERVER:1433")]
+    #[case("SQLSERVER\\PROWATCH", "SQLSERVER:1434")]
+    fn named_instances_are_resolved_through_the_sql_browser(
+        #[case] host: &str,
+        #[case] expected_addr: &str,
+    ) {
+        let config = host_connection_config(&Config::new(), host, 1433);
+        assert_eq!(config.get_addr(), expected_addr);
+    }
+
+    #[test]
+    fn invalid_connection_strings_are_refused() {
+        let db_config = DatabaseSettings {
+            connection_string: Some("Server=tcp:SQLSERVER,port".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            setup_database_pool(db_config),
+            Err(DatabaseSetupError::InvalidConnectionString(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn timed_out_and_dropped_queries_are_recorded() {
+        let mut pool = setup_database_pool(DatabaseSettings::default()).unwrap();
+        pool.query_timeout = Some(Duration::from_millis(10));
+        // Without a subscriber the span has no name to label the query with
+        let _subscriber = tracing::subscriber::set_default(tracing_subscriber::registry());
+        let span = info_span!("Slow query");
+        let _entered = span.enter();
+        assert!(matches!(
+            pool.timed(std::future::pending::<Result<(), ApplicationError>>())
+                .await,
+            Err(ApplicationError::QueryTimeout)
+        ));
+        // A query cancelled by the request time limit is dropped before it completes
+        pool.query_timeout = None;
+        let _ = tokio::time::timeout(
+            Duration::from_millis(10),
+            pool.timed(std::future::pending::<Result<(), ApplicationError>>()),
+        )
+        .await;
+
+        let label = QueryLabel {
+            query: "Slow query".to_string(),
+        };
+        assert_eq!(pool.query_timeouts.get_or_create(&label).get(), 1);
+        let mut registry = Registry::default();
+        registry.register("durations", "", pool.query_durations.clone());
+        let mut text = String::new();
+        encode(&mut text, &registry).unwrap();
+        assert!(text.contains("durations_count{query=\"Slow query\"} 2"));
     }
-    Ok(Vec::new())
 }
@@ -1,6 +1,11 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{
+    http::{HeaderValue, StatusCode, header::WWW_AUTHENTICATE},
+    response::{AppendHeaders, IntoResponse},
+};
 use bb8::RunError;
 
+use crate::validation::InvalidSettings;
+
 /// Runtime Errors
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum ApplicationError {
@@ -23,6 +28,74 @@ pub(crate) enum ApplicationError {
     /// Error during Conversion from a Database Value
     #[error("Empty Result received from DB")]
     EmptyResult,
+
+    /// The requested Endpoint is deactivated in the config
+    #[error("Endpoint is deactivated")]
+    Deactivated,
+
+    /// A Database query took longer than the configured query timeout
+    #[error("Database query timed out")]
+    QueryTimeout,
+
+    /// The requested resource does not exist
+    #[error("Not found")]
+    NotFound,
+
+    /// The request lacks a valid token or user
+    #[error("Valid credentials are required")]
+    Unauthorized,
+
+    /// The endpoint is only answered with a client certificate
+    #[error("A client certificate is required")]
+    ClientCertificateRequired,
+
+    /// The request body does not describe valid settings
+    #[error("{0}")]
+    InvalidRequest(String),
+
+    /// The requested change would make the `Settings` invalid
+    #[error(transparent)]
+    InvalidSettings(#[from] InvalidSettings),
+}
+
+/// Errors while setting up the Database Connection Pool
+#[derive(thiserror::Error, Debug)]
+pub enum DatabaseSetupError {
+    /// The pool settings would make the pool unusable
+    #[error("Invalid database pool settings: {0}")]
+    InvalidPoolSettings(String),
+
+    /// A failover partner is neither `host` nor `host:port`
+    #[error("Invalid failover partner: {0:?}")]
+    InvalidFailoverPartner(String),
+
+    /// The connection string could not be parsed as an ADO.NET connection string
+    #[error("Invalid connection string: {0}")]
+    InvalidConnectionString(tiberius::error::Error),
+
+    /// The authentication method is not available on this platform
+    #[error("Unsupported authentication method: {0}")]
+    UnsupportedAuthMethod(String),
+}
+
+/// Errors while loading the TLS certificate, its key and the client CAs
+#[derive(thiserror::Error, Debug)]
+pub enum TlsSetupError {
+    /// A PEM file could not be read or contains nothing usable
+    #[error("Could not read {path}: {message}")]
+    InvalidPemFile { path: String, message: String },
+
+    /// The certificate could not be parsed to read its expiry
+    #[error("Invalid certificate in {path}: {message}")]
+    InvalidCertificate { path: String, message: String },
+
+    /// rustls refused the certificate, the key or a client CA
+    #[error("Invalid TLS configuration: {0}")]
+    Rustls(#[from] rustls::Error),
+
+    /// No client certificate verifier could be built from the client CAs
+    #[error("Invalid client CAs: {0}")]
+    ClientVerifier(#[from] rustls::server::VerifierBuilderError),
 }
 
 impl IntoResponse for ApplicationError {
@@ -63,6 +136,35 @@ impl IntoResponse for ApplicationError {
                     "Empty Result received from DB".to_string(),
                 )
             }
+            Self::Deactivated => (StatusCode::NOT_FOUND, "nothing to see here".to_string()),
+            Self::QueryTimeout => {
+                tracing::error!("Database query timed out");
+                (
+                    StatusCode::GATEWAY_TIMEOUT,
+                    "Database query timed out".to_string(),
+                )
+            }
+            Self::Unauthorized => {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    AppendHeaders([
+                        (WWW_AUTHENTICATE, HeaderValue::from_static("Bearer")),
+                        (
+                            WWW_AUTHENTICATE,
+                            HeaderValue::from_static("Basic realm=\"maedic\""),
+                        ),
+                    ]),
+                    "Valid credentials are required",
+                )
+                    .into_response();
+            }
+            Self::NotFound => (StatusCode::NOT_FOUND, "nothing to see here".to_string()),
+            Self::ClientCertificateRequired => (
+                StatusCode::FORBIDDEN,
+                "A client certificate is required".to_string(),
+            ),
+            Self::InvalidRequest(message) => (StatusCode::BAD_REQUEST, message),
+            Self::InvalidSettings(err) => (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()),
         };
         (status, message).into_response()
     }
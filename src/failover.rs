@@ -0,0 +1,136 @@
+use bb8::ManageConnection;
+use bb8_tiberius::{ConnectionManager, Error, rt::Client};
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+/// `FailoverConnectionManager` connects to the primary out of the configured host and its failover partners
+///
+/// With a single host every connection is accepted, just like the plain `ConnectionManager`.
+/// With failover partners a host only counts if it holds the primary replica of the PW database,
+/// so maedic follows the database after an AlwaysOn failover or a mirroring role switch.
+pub struct FailoverConnectionManager {
+    managers: Vec<Arc<ConnectionManager>>,
+    hosts: Vec<String>,
+    multi_subnet_failover: bool,
+    /// Index of the host the last connection was established to, it is tried first
+    current: AtomicUsize,
+}
+
+impl FailoverConnectionManager {
+    /// `managers` and `hosts` are in the same order, the first one being the configured `hostname`
+    pub(crate) fn new(
+        managers: Vec<ConnectionManager>,
+        hosts: Vec<String>,
+        multi_subnet_failover: bool,
+    ) -> Self {
+        Self {
+            managers: managers.into_iter().map(Arc::new).collect(),
+            hosts,
+            multi_subnet_failover,
+            current: AtomicUsize::new(0),
+        }
+    }
+
+    /// Connect to a single host and make sure it holds the primary replica if that matters
+    async fn connect_to_primary(
+        manager: Arc<ConnectionManager>,
+        check_role: bool,
+    ) -> Result<Client, Error> {
+        let mut client = manager.connect().await?;
+        if check_role && !is_primary_replica(&mut client).await {
+            return Err(std::io::Error::other("host does not hold the primary replica").into());
+        }
+        Ok(client)
+    }
+
+    /// Try all hosts at once and take the first primary, like `MultiSubnetFailover=True`
+    async fn connect_parallel(&self, check_role: bool) -> Result<(usize, Client), Error> {
+        let mut attempts = JoinSet::new();
+        for (index, manager) in self.managers.iter().enumerate() {
+            let manager = manager.clone();
+            attempts
+                .spawn(async move { (index, Self::connect_to_primary(manager, check_role).await) });
+        }
+        let mut last_error = None;
+        while let Some(attempt) = attempts.join_next().await {
+            match attempt {
+                Ok((index, Ok(client))) => return Ok((index, client)),
+                Ok((index, Err(err))) => {
+                    warn!("Could not connect to {}: {:?}", self.hosts[index], err);
+                    last_error = Some(err);
+                }
+                Err(err) => last_error = Some(std::io::Error::other(err).into()),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| std::io::Error::other("no host configured").into()))
+    }
+
+    /// Try the hosts one after another, starting with the last one that worked
+    async fn connect_sequential(&self, check_role: bool) -> Result<(usize, Client), Error> {
+        let current = self.current.load(Ordering::Relaxed);
+        let mut last_error = None;
+        for offset in 0..self.managers.len() {
+            let index = (current + offset) % self.managers.len();
+            match Self::connect_to_primary(self.managers[index].clone(), check_role).await {
+                Ok(client) => return Ok((index, client)),
+                Err(err) => {
+                    warn!("Could not connect to {}: {:?}", self.hosts[index], err);
+                    last_error = Some(err);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| std::io::Error::other("no host configured").into()))
+    }
+}
+
+impl ManageConnection for FailoverConnectionManager {
+    type Connection = Client;
+    type Error = Error;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let check_role = self.managers.len() > 1;
+        let (index, client) = if self.multi_subnet_failover {
+            self.connect_parallel(check_role).await?
+        } else {
+            self.connect_sequential(check_role).await?
+        };
+        if self.current.swap(index, Ordering::Relaxed) != index {
+            info!("Connected to {}", self.hosts[index]);
+        }
+        Ok(client)
+    }
+
+    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.managers[0].is_valid(conn).await
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.managers[0].has_broken(conn)
+    }
+}
+
+/// Whether the connected host holds the primary replica of the PW database
+/// Databases outside of an availability group and missing permissions count as primary
+async fn is_primary_replica(client: &mut Client) -> bool {
+    let row = match client
+        .simple_query(
+            "select CAST(is_primary_replica as int) as 'is_primary_replica'
+            from sys.dm_hadr_database_replica_states
+            where database_id = DB_ID() and is_local = 1",
+        )
+        .await
+    {
+        Ok(stream) => stream.into_row().await,
+        Err(err) => Err(err),
+    };
+    match row {
+        Ok(Some(row)) => row
+            .get::<i32, &str>("is_primary_replica")
+            .is_none_or(|is_primary| is_primary == 1),
+        _ => true,
+    }
+}
@@ -1,45 +1,159 @@
+use anyhow::Context;
 use axum::BoxError;
 use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::extract::Path as UrlPath;
 use axum::http::header::CONTENT_TYPE;
-use axum::http::{Method, Uri};
+use axum::http::{HeaderMap, Method, Uri};
 use axum::http::{Response, StatusCode};
 use axum::response::IntoResponse;
 use axum::{Json, extract::State};
+use chrono::Utc;
 use prometheus_client::encoding::text::encode;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::warn;
+use tracing::{info, warn};
+use uuid::Uuid;
 
 use crate::metrics::{Endpoint, collect_metrics};
 use crate::{
+    auth::{VerifiedPasswords, authenticate},
+    capacity::CapacityReport,
     checks::{check_local_service, get_cpu_load, get_ram_load},
-    configuration::LimitSettings,
-    database::{DatabaseConnectionState, get_db_status, get_table_count, get_unhealthy_spoolfiles},
+    configuration::{AuthSettings, LimitSettings},
+    database::{
+        DatabaseConnectionState, get_backup_health, get_connected_workstation_count, get_db_status,
+        get_event_log_retention, get_failed_logins, get_hiqueue_count_per_panel,
+        get_mssql_activity, get_recent_audit_log_entries, get_replica_states, get_table_count,
+        get_unhealthy_spoolfiles,
+    },
     error::ApplicationError,
-    health::{MaedicHealth, PWHealth, health_is_good},
+    health::{MaedicHealth, PWHealth, failed_checks, health_is_good},
+    model::audit_log::AuditLogEntry,
     run::AppState,
+    silence::{NewSilence, Silence, silenced_checks},
+    template::limits_config,
 };
 
 /// Handler to check the Health of PW
 #[tracing::instrument(name = "check PW health", skip_all)]
 pub(crate) async fn check_health(
     State(state): State<Arc<Mutex<AppState>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> Result<(StatusCode, Json<PWHealth>), ApplicationError> {
+    authorize(&state, Endpoint::Health, address, &headers).await?;
     let mut state = state.lock().await;
-    state.metrics.inc_requests(Endpoint::Health);
+    let health = gather_health(&mut state).await?;
+    if !health_is_good(&health, &state.settings.limits) {
+        warn!("App reported unhealthy status {:?}", health);
+        Ok((StatusCode::SERVICE_UNAVAILABLE, Json(health)))
+    } else {
+        Ok((StatusCode::OK, Json(health)))
+    }
+}
+
+/// Gather the `PWHealth` values for every enabled check
+pub(crate) async fn gather_health(state: &mut AppState) -> Result<PWHealth, ApplicationError> {
     let limits = state.settings.limits.clone();
+    // Checks against the database are skipped while it is unreachable
+    let database_reachable = state.database_connection == DatabaseConnectionState::Healthy;
     // HI_QUEUE
-    let hi_queue_size = if limits.hi_queue_count == 0 {
+    let hi_queue_size = if !database_reachable || limits.hi_queue_count == 0 {
         None
     } else {
         Some(get_table_count(state.pool.clone(), "hi_queue".to_string()).await?)
     };
+    // HI_QUEUE per panel, so the panels can be silenced on their own
+    let hi_queue_per_panel = match hi_queue_size {
+        Some(hi_queue_size) if hi_queue_size > limits.hi_queue_count => {
+            Some(get_hiqueue_count_per_panel(state.pool.clone()).await?)
+        }
+        _ => None,
+    };
     //Spool Files
-    let unhealthy_spool_files = if limits.spool_file_count == 0 {
+    let unhealthy_spool_files = if !database_reachable || limits.spool_file_count == 0 {
         None
     } else {
         Some(get_unhealthy_spoolfiles(state.pool.clone(), limits.spool_file_count).await?)
     };
+    // Connected Workstations
+    let connected_workstations = if !database_reachable || limits.min_connected_workstations == 0 {
+        None
+    } else {
+        Some(
+            get_connected_workstation_count(
+                state.pool.clone(),
+                state.settings.metrics.session_stale_minutes,
+                &state.settings.metrics.alarm_workstations,
+            )
+            .await?,
+        )
+    };
+    // Failed Operator logins
+    let failed_login_attempts = if !database_reachable || limits.max_failed_login_attempts == 0 {
+        None
+    } else {
+        Some(
+            get_failed_logins(
+                state.pool.clone(),
+                state.settings.metrics.failed_login_window_minutes,
+            )
+            .await?
+            .iter()
+            .map(|failed_login| failed_login.failed_attempts)
+            .sum(),
+        )
+    };
+    // Event Log retention and archive
+    let (oldest_event_age_days, event_log_archive) = if !database_reachable
+        || limits.max_event_log_age_days == 0 && limits.max_days_since_last_archive == 0
+    {
+        (None, None)
+    } else {
+        let retention = get_event_log_retention(state.pool.clone()).await?;
+        (
+            (limits.max_event_log_age_days != 0)
+                .then(|| retention.oldest_event_age_days.unwrap_or_default()),
+            (limits.max_days_since_last_archive != 0).then_some(retention.archive),
+        )
+    };
+    // SQL Server activity
+    let (blocked_sessions, long_running_requests) = if !database_reachable
+        || limits.max_blocked_sessions == 0 && limits.max_long_running_requests == 0
+    {
+        (None, None)
+    } else {
+        let activity = get_mssql_activity(
+            state.pool.clone(),
+            state.settings.metrics.long_running_request_seconds,
+        )
+        .await?;
+        (
+            (limits.max_blocked_sessions != 0).then_some(activity.blocked_sessions),
+            (limits.max_long_running_requests != 0).then_some(activity.long_running_requests),
+        )
+    };
+    // Backups
+    let backups = if !database_reachable
+        || limits.max_full_backup_age_hours == 0
+            && limits.max_differential_backup_age_hours == 0
+            && limits.max_log_backup_age_hours == 0
+    {
+        None
+    } else {
+        Some(get_backup_health(state.pool.clone()).await?)
+    };
+    // Availability group replicas
+    let replicas = if !database_reachable || !limits.check_replica_synchronization {
+        None
+    } else {
+        Some(get_replica_states(state.pool.clone()).await?)
+    };
     state.sys.refresh_all();
     let service_state = if !limits.check_local_service {
         None
@@ -58,61 +172,418 @@ pub(crate) async fn check_health(
         Some(get_ram_load(&state.sys).await)
     };
 
-    let maedic_health = match get_db_status(state.pool.clone()).await {
-        Ok(state) => match state {
-            DatabaseConnectionState::Healthy => MaedicHealth::healthy(),
-            DatabaseConnectionState::Unhealthy => MaedicHealth::unhealthy(),
-        },
-        Err(_) => MaedicHealth::unhealthy(),
+    let maedic_health = if !database_reachable {
+        MaedicHealth::unhealthy()
+    } else {
+        match get_db_status(state.pool.clone()).await {
+            Ok(state) => match state {
+                DatabaseConnectionState::Healthy => MaedicHealth::healthy(),
+                DatabaseConnectionState::Unhealthy => MaedicHealth::unhealthy(),
+            },
+            Err(_) => MaedicHealth::unhealthy(),
+        }
     };
 
-    let health = PWHealth {
+    let mut health = PWHealth {
         unhealthy_spool_files,
         hi_queue_size,
+        hi_queue_per_panel,
         service_state,
         global_cpu_usage_percentage,
         used_memory_percentage,
+        connected_workstations,
+        failed_login_attempts,
+        oldest_event_age_days,
+        event_log_archive,
+        blocked_sessions,
+        long_running_requests,
+        backups,
+        replicas,
         maedic_health,
+        silenced_checks: Vec::new(),
     };
-    if !health_is_good(&health, &limits) {
-        warn!("App reported unhealthy status {:?}", health);
-        Ok((StatusCode::SERVICE_UNAVAILABLE, Json(health)))
-    } else {
-        Ok((StatusCode::OK, Json(health)))
-    }
+    // Silenced checks are still evaluated and shown, they only do not affect the status code
+    let now = Utc::now();
+    state.silences.retain(|silence| silence.expires_at > now);
+    health.silenced_checks = silenced_checks(
+        &health,
+        &failed_checks(&health, &limits),
+        &limits,
+        &state.settings.maintenance.windows,
+        &state.silences,
+        now.into(),
+    );
+    Ok(health)
 }
 
 /// Exposing the `LimitSettings` for the health check endpoint
 #[tracing::instrument(name = "Getting exposed config", skip_all)]
 pub(crate) async fn get_config_handler(
     State(state): State<Arc<Mutex<AppState>>>,
-) -> Result<(StatusCode, Json<LimitSettings>), StatusCode> {
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<LimitSettings>), ApplicationError> {
+    authorize(&state, Endpoint::Config, address, &headers).await?;
     let state = state.lock().await;
-    state.metrics.inc_requests(Endpoint::Config);
     if state.settings.application.expose_config {
         Ok((StatusCode::OK, Json(state.settings.limits.clone())))
     } else {
-        Err(StatusCode::NOT_FOUND)
+        Err(ApplicationError::Deactivated)
+    }
+}
+
+/// Listing the unexpired silences
+#[tracing::instrument(name = "Listing silences", skip_all)]
+pub(crate) async fn get_silences_handler(
+    State(state): State<Arc<Mutex<AppState>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<Vec<Silence>>), ApplicationError> {
+    authorize(&state, Endpoint::Silences, address, &headers).await?;
+    let state = state.lock().await;
+    let now = Utc::now();
+    let silences = state
+        .silences
+        .iter()
+        .filter(|silence| silence.expires_at > now)
+        .cloned()
+        .collect();
+    Ok((StatusCode::OK, Json(silences)))
+}
+
+/// Silence checks, single channels or single panels until the silence expires
+#[tracing::instrument(name = "Creating silence", skip_all)]
+pub(crate) async fn post_silence_handler(
+    State(state): State<Arc<Mutex<AppState>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(new_silence): Json<NewSilence>,
+) -> Result<(StatusCode, Json<Silence>), ApplicationError> {
+    let author = authenticate_change(&state, Endpoint::Silences, address, &headers).await?;
+    let mut state = state.lock().await;
+    let silence = new_silence
+        .into_silence(author, Utc::now())
+        .map_err(ApplicationError::InvalidRequest)?;
+    info!(
+        "Silence {} created by {} from {address} until {}: checks [{}], channels [{}], panels [{}], {}",
+        silence.id,
+        silence.created_by,
+        silence.expires_at,
+        silence
+            .checks
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        silence.channels.join(", "),
+        silence.panels.join(", "),
+        silence.comment
+    );
+    state.silences.push(silence.clone());
+    Ok((StatusCode::CREATED, Json(silence)))
+}
+
+/// Remove a silence before it expires
+#[tracing::instrument(name = "Removing silence", skip_all)]
+pub(crate) async fn delete_silence_handler(
+    State(state): State<Arc<Mutex<AppState>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    UrlPath(id): UrlPath<Uuid>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApplicationError> {
+    let author = authenticate_change(&state, Endpoint::Silences, address, &headers).await?;
+    let mut state = state.lock().await;
+    let count = state.silences.len();
+    state.silences.retain(|silence| silence.id != id);
+    if state.silences.len() == count {
+        return Err(ApplicationError::NotFound);
+    }
+    info!("Silence {id} removed by {author} from {address}");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Count the request to the `endpoint` and check the credentials if it is protected
+async fn authorize(
+    state: &Arc<Mutex<AppState>>,
+    endpoint: Endpoint,
+    address: SocketAddr,
+    headers: &HeaderMap,
+) -> Result<(), ApplicationError> {
+    let (auth, verified_passwords) = {
+        let state = state.lock().await;
+        state.metrics.inc_requests(endpoint);
+        (
+            state.settings.auth.clone(),
+            state.verified_passwords.clone(),
+        )
+    };
+    if auth.protected_endpoints.contains(&endpoint) {
+        authenticate_request(state, auth, verified_passwords, endpoint, address, headers).await?;
+    }
+    Ok(())
+}
+
+/// Count the request to the `endpoint` and return the name of the token or user authorizing a change
+/// Changes are deactivated without any credentials
+async fn authenticate_change(
+    state: &Arc<Mutex<AppState>>,
+    endpoint: Endpoint,
+    address: SocketAddr,
+    headers: &HeaderMap,
+) -> Result<String, ApplicationError> {
+    let (auth, verified_passwords) = {
+        let state = state.lock().await;
+        state.metrics.inc_requests(endpoint);
+        (
+            state.settings.auth.clone(),
+            state.verified_passwords.clone(),
+        )
+    };
+    if auth.tokens.is_empty() && auth.users.is_empty() {
+        return Err(ApplicationError::Deactivated);
+    }
+    authenticate_request(state, auth, verified_passwords, endpoint, address, headers).await
+}
+
+/// The name of the token or user sending the request, a refused request is logged and counted
+/// Verifying a password takes a while, so it runs on a blocking thread without holding the `AppState` lock
+async fn authenticate_request(
+    state: &Arc<Mutex<AppState>>,
+    auth: AuthSettings,
+    verified_passwords: VerifiedPasswords,
+    endpoint: Endpoint,
+    address: SocketAddr,
+    headers: &HeaderMap,
+) -> Result<String, ApplicationError> {
+    let headers = headers.clone();
+    let authenticated = tokio::task::spawn_blocking(move || {
+        authenticate(&headers, &auth, &verified_passwords).map(str::to_string)
+    })
+    .await
+    .context("Checking the credentials")?;
+    if authenticated.is_err() {
+        warn!("Refused a request to {endpoint:?} from {address} without valid credentials");
+        state.lock().await.metrics.inc_auth_failures(endpoint);
+    }
+    authenticated
+}
+
+/// Replace the `LimitSettings`, missing values take their default
+#[tracing::instrument(name = "Replacing limits", skip_all)]
+pub(crate) async fn put_limits_handler(
+    State(state): State<Arc<Mutex<AppState>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(limits): Json<Value>,
+) -> Result<(StatusCode, Json<LimitSettings>), ApplicationError> {
+    change_limits(&state, address, &headers, limits, true).await
+}
+
+/// Change single values of the `LimitSettings`
+#[tracing::instrument(name = "Changing limits", skip_all)]
+pub(crate) async fn patch_limits_handler(
+    State(state): State<Arc<Mutex<AppState>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(changes): Json<Value>,
+) -> Result<(StatusCode, Json<LimitSettings>), ApplicationError> {
+    change_limits(&state, address, &headers, changes, false).await
+}
+
+/// Validate, persist and apply the changed limits, logging the author and every changed value
+async fn change_limits(
+    state: &Arc<Mutex<AppState>>,
+    address: SocketAddr,
+    headers: &HeaderMap,
+    changes: Value,
+    replace: bool,
+) -> Result<(StatusCode, Json<LimitSettings>), ApplicationError> {
+    let author = authenticate_change(state, Endpoint::ConfigLimits, address, headers).await?;
+    let mut state = state.lock().await;
+
+    let Value::Object(changes) = changes else {
+        return Err(ApplicationError::InvalidRequest(
+            "Expected a JSON object with the limits".to_string(),
+        ));
+    };
+    let current = serde_json::to_value(&state.settings.limits).context("Serializing limits")?;
+    let mut limits = if replace {
+        serde_json::Map::new()
+    } else {
+        match current.clone() {
+            Value::Object(limits) => limits,
+            _ => serde_json::Map::new(),
+        }
+    };
+    limits.extend(changes);
+    let mut unknown_keys = Vec::new();
+    let limits: LimitSettings = serde_ignored::deserialize(Value::Object(limits), |path| {
+        unknown_keys.push(path.to_string())
+    })
+    .map_err(|err| ApplicationError::InvalidRequest(format!("Invalid limits: {err}")))?;
+    if !unknown_keys.is_empty() {
+        return Err(ApplicationError::InvalidRequest(format!(
+            "Unknown limits: {}",
+            unknown_keys.join(", ")
+        )));
+    }
+    let mut settings = state.settings.clone();
+    settings.limits = limits.clone();
+    settings.validate()?;
+
+    let changed = changed_values(
+        &current,
+        &serde_json::to_value(&limits).context("Serializing limits")?,
+    );
+    if !settings.application.limits_file.is_empty() {
+        persist_limits(
+            Path::new(&settings.application.limits_file),
+            &limits,
+            changed.iter().map(|(key, _)| key.clone()),
+        )?;
+    }
+    state.settings.limits = limits.clone();
+    if changed.is_empty() {
+        info!("Limits left unchanged by {author} from {address}");
+    } else {
+        info!(
+            "Limits changed by {author} from {address}: {}",
+            changed
+                .into_iter()
+                .map(|(_, change)| change)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok((StatusCode::OK, Json(limits)))
+}
+
+/// Write the limits to a temporary file first, so a failed write never leaves a broken limits file behind
+/// Only the limits ever changed via the API are written, together with the ones already in the file
+fn persist_limits(
+    path: &Path,
+    limits: &LimitSettings,
+    changed: impl Iterator<Item = String>,
+) -> Result<(), ApplicationError> {
+    let mut persisted = persisted_limits(path);
+    persisted.extend(changed);
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, limits_config(limits, &persisted))
+        .and_then(|()| std::fs::rename(&temporary, path))
+        .with_context(|| format!("Writing the limits to {}", path.display()))?;
+    Ok(())
+}
+
+/// The limits already written to the `limits_file`, none if it does not exist yet
+fn persisted_limits(path: &Path) -> BTreeSet<String> {
+    config::Config::builder()
+        .add_source(config::File::from(path).required(false))
+        .build()
+        .and_then(|config| config.get_table("limits"))
+        .map(|limits| limits.into_keys().collect())
+        .unwrap_or_default()
+}
+
+/// The keys of the values differing between two serialized settings with their change as `key: old -> new`
+fn changed_values(old: &Value, new: &Value) -> Vec<(String, String)> {
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        return Vec::new();
+    };
+    new.iter()
+        .filter_map(|(key, value)| match old.get(key) {
+            Some(old_value) if old_value == value => None,
+            Some(old_value) => Some((key.clone(), format!("{key}: {old_value} -> {value}"))),
+            None => Some((key.clone(), format!("{key}: {value}"))),
+        })
+        .collect()
+}
+
+/// Exposing the most recent configuration changes from the audit log
+#[tracing::instrument(name = "Getting recent audit log entries", skip_all)]
+pub(crate) async fn get_audit_log_handler(
+    State(state): State<Arc<Mutex<AppState>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<Vec<AuditLogEntry>>), ApplicationError> {
+    authorize(&state, Endpoint::Audit, address, &headers).await?;
+    let state = state.lock().await;
+    let application = &state.settings.application;
+    if application.audit_log_entries == 0 {
+        return Err(ApplicationError::Deactivated);
+    }
+    let entries = get_recent_audit_log_entries(
+        state.pool.clone(),
+        application.audit_log_entries,
+        application.redact_audit_images,
+    )
+    .await?;
+    Ok((StatusCode::OK, Json(entries)))
+}
+
+/// Exposing the growth forecast of the database files and the big tables
+#[tracing::instrument(name = "Getting capacity forecast", skip_all)]
+pub(crate) async fn get_capacity_handler(
+    State(state): State<Arc<Mutex<AppState>>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<CapacityReport>), ApplicationError> {
+    authorize(&state, Endpoint::Capacity, address, &headers).await?;
+    let state = state.lock().await;
+    if state.settings.metrics.capacity_sample_interval_minutes == 0 {
+        return Err(ApplicationError::Deactivated);
     }
+    Ok((StatusCode::OK, Json(state.capacity.report())))
 }
 
 /// Exposing Prometheus style metrics collected from the database
-#[tracing::instrument(name = "Scrape metrics", skip(state))]
+#[tracing::instrument(name = "Scrape metrics", skip(state, headers))]
 pub(crate) async fn metrics_handler(
     State(state): State<Arc<Mutex<AppState>>>,
-) -> impl IntoResponse {
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, ApplicationError> {
+    authorize(&state, Endpoint::Metrics, address, &headers).await?;
     let state = state.lock().await;
-    state.metrics.inc_requests(Endpoint::Metrics);
-    collect_metrics(state.pool.clone(), &state.metrics)
-        .await
-        .unwrap();
-    let mut buffer = String::new();
-    encode(&mut buffer, &state.registry).unwrap();
-    Response::builder()
+    if let Err(err) = refresh_metrics(&state).await {
+        warn!("Could not collect metrics from the database: {:?}", err);
+    }
+    Ok(Response::builder()
         .status(StatusCode::OK)
         .header(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")
-        .body(Body::from(buffer))
-        .unwrap()
+        .body(Body::from(encode_metrics(&state)))
+        .unwrap())
+}
+
+/// Collect the metrics from the database and update the pool state
+/// The metrics of maedic itself are still exposed while the database is unreachable
+pub(crate) async fn refresh_metrics(state: &AppState) -> Result<(), ApplicationError> {
+    let collected = if state.database_connection == DatabaseConnectionState::Healthy {
+        collect_metrics(state.pool.clone(), &state.metrics, &state.settings.metrics).await
+    } else {
+        Ok(())
+    };
+    state.metrics.set_pool_state(state.pool.state());
+    state.metrics.set_silences(
+        &state.settings.maintenance.windows,
+        &state.silences,
+        Utc::now(),
+    );
+    if let Some(tls) = &state.tls {
+        state.metrics.set_tls_certificate_expiry(
+            tls.certificate_file(),
+            tls.expires_at(),
+            Utc::now(),
+        );
+    }
+    collected
+}
+
+/// Encode the registry in the Prometheus text format
+pub(crate) fn encode_metrics(state: &AppState) -> String {
+    let mut buffer = String::new();
+    encode(&mut buffer, &state.registry).unwrap();
+    buffer
 }
 
 /// Exposing Prometheus style metrics collected from the database
@@ -4,8 +4,15 @@ use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 
 use crate::{
-    configuration::LimitSettings, database::DatabaseConnectionState,
-    model::spoolfile::SpoolFileCount,
+    configuration::LimitSettings,
+    database::DatabaseConnectionState,
+    model::{
+        backup::{BackupHealth, BackupState, BackupType},
+        event_log::EventLogArchive,
+        hi_queue::HiQueueCount,
+        mssql::ReplicaState,
+        spoolfile::SpoolFileCount,
+    },
 };
 
 /// Health components of the connected PW instance
@@ -15,7 +22,15 @@ use crate::{
 /// - Spool Files (unfinished downloads to the hardware)
 /// - Service_State (the status of the PW Windows Service)
 /// - Checks for CPU and RAM usage
+/// - Connected Workstations (PW clients with an active session)
+/// - Failed Operator logins
+/// - Event Log retention (age of the oldest event) and the last archive run
+/// - Blocked sessions and long-running requests on the SQL Server
+/// - Age of the last full, differential and log backup
+/// - Synchronization of the availability group replicas
 /// - Health of Maedic itself, checking the DB Connection
+///
+/// Failed checks covered by a maintenance window or a silence are listed in `silenced_checks`
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct PWHealth {
     pub maedic_health: MaedicHealth,
@@ -23,7 +38,20 @@ pub struct PWHealth {
     pub global_cpu_usage_percentage: Option<f32>,
     pub used_memory_percentage: Option<f32>,
     pub hi_queue_size: Option<i32>,
+    /// Entries of the HI_QUEUE per panel, only gathered once the `hi_queue_size` is above the limit
+    pub hi_queue_per_panel: Option<Vec<HiQueueCount>>,
     pub unhealthy_spool_files: Option<Vec<SpoolFileCount>>,
+    pub connected_workstations: Option<i32>,
+    pub failed_login_attempts: Option<i32>,
+    pub oldest_event_age_days: Option<i32>,
+    pub event_log_archive: Option<EventLogArchive>,
+    pub blocked_sessions: Option<i32>,
+    pub long_running_requests: Option<i32>,
+    pub backups: Option<BackupHealth>,
+    pub replicas: Option<Vec<ReplicaState>>,
+    /// Failed checks silenced by a maintenance window or a silence, they do not affect the status code
+    #[serde(default)]
+    pub silenced_checks: Vec<HealthCheck>,
 }
 
 /// The Health of Maedic itself
@@ -85,43 +113,153 @@ pub enum ServiceState {
     Down,
 }
 
+/// `HealthCheck` lists the checks evaluated for the `PWHealth`
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, strum_macros::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum HealthCheck {
+    DatabaseConnection,
+    HiQueueSize,
+    SpoolFiles,
+    ServiceState,
+    CpuUsage,
+    MemoryUsage,
+    ConnectedWorkstations,
+    FailedLoginAttempts,
+    EventLogAge,
+    EventLogArchive,
+    BlockedSessions,
+    LongRunningRequests,
+    Backups,
+    ReplicaSynchronization,
+}
+
 #[tracing::instrument(name = "Determine Health Status with gathered parameters", skip_all)]
 pub(crate) fn health_is_good(health: &PWHealth, limits: &LimitSettings) -> bool {
+    failed_checks(health, limits)
+        .iter()
+        .all(|check| health.silenced_checks.contains(check))
+}
+
+/// The checks whose gathered value violates its limit, skipped checks never fail
+pub(crate) fn failed_checks(health: &PWHealth, limits: &LimitSettings) -> Vec<HealthCheck> {
+    let mut failed = Vec::new();
+    // Database connection
+    if health.maedic_health.database_connection != DatabaseConnectionState::Healthy {
+        failed.push(HealthCheck::DatabaseConnection);
+    };
+
     // HI_QUEUE
     if let Some(hi_queue_size) = health.hi_queue_size
         && hi_queue_size > limits.hi_queue_count
     {
-        return false;
+        failed.push(HealthCheck::HiQueueSize);
     };
 
     // Spool Files
     if let Some(unhealthy_spool_files) = &health.unhealthy_spool_files
         && !unhealthy_spool_files.is_empty()
     {
-        return false;
+        failed.push(HealthCheck::SpoolFiles);
     };
 
     // Service State
     if let Some(service_state) = &health.service_state
         && service_state != &ServiceState::Up
     {
-        return false;
+        failed.push(HealthCheck::ServiceState);
     };
 
     // CPU
     if let Some(cpu_value) = health.global_cpu_usage_percentage
         && cpu_value > limits.max_cpu_percentage
     {
-        return false;
+        failed.push(HealthCheck::CpuUsage);
     };
 
     // RAM
     if let Some(ram_value) = health.used_memory_percentage
         && ram_value > limits.max_ram_percentage
     {
-        return false;
+        failed.push(HealthCheck::MemoryUsage);
+    };
+
+    // Connected Workstations
+    if let Some(connected_workstations) = health.connected_workstations
+        && connected_workstations < limits.min_connected_workstations
+    {
+        failed.push(HealthCheck::ConnectedWorkstations);
+    };
+
+    // Failed Operator logins
+    if let Some(failed_login_attempts) = health.failed_login_attempts
+        && failed_login_attempts > limits.max_failed_login_attempts
+    {
+        failed.push(HealthCheck::FailedLoginAttempts);
+    };
+
+    // Event Log retention
+    if let Some(oldest_event_age_days) = health.oldest_event_age_days
+        && oldest_event_age_days > limits.max_event_log_age_days
+    {
+        failed.push(HealthCheck::EventLogAge);
+    };
+
+    // Event Log archive, a missing archive run counts as too old
+    if let Some(archive) = &health.event_log_archive
+        && (archive.last_archive_failed
+            || limits.max_days_since_last_archive != 0
+                && archive
+                    .days_since_last_archive
+                    .is_none_or(|days| days > limits.max_days_since_last_archive))
+    {
+        failed.push(HealthCheck::EventLogArchive);
     };
-    true
+
+    // Blocked sessions
+    if let Some(blocked_sessions) = health.blocked_sessions
+        && blocked_sessions > limits.max_blocked_sessions
+    {
+        failed.push(HealthCheck::BlockedSessions);
+    };
+
+    // Long-running requests
+    if let Some(long_running_requests) = health.long_running_requests
+        && long_running_requests > limits.max_long_running_requests
+    {
+        failed.push(HealthCheck::LongRunningRequests);
+    };
+
+    // Backups
+    if let Some(backups) = &health.backups
+        && (backups.state != BackupState::Readable
+            || [
+                (BackupType::Full, limits.max_full_backup_age_hours),
+                (
+                    BackupType::Differential,
+                    limits.max_differential_backup_age_hours,
+                ),
+                (BackupType::Log, limits.max_log_backup_age_hours),
+            ]
+            .into_iter()
+            // A missing backup only counts if the check is active for this type
+            .any(|(backup_type, limit)| {
+                limit != 0
+                    && backups
+                        .age_hours(&backup_type)
+                        .is_none_or(|age| age > limit)
+            }))
+    {
+        failed.push(HealthCheck::Backups);
+    };
+
+    // Availability group replicas
+    if let Some(replicas) = &health.replicas
+        && replicas.iter().any(|replica| !replica.is_synchronized())
+    {
+        failed.push(HealthCheck::ReplicaSynchronization);
+    };
+    failed
 }
 
 #[cfg(test)]
@@ -134,10 +272,33 @@ mod tests {
         fn default() -> Self {
             Self {
                 hi_queue_size: Some(0),
+                hi_queue_per_panel: None,
                 unhealthy_spool_files: Some(Vec::new()),
                 service_state: Some(ServiceState::Up),
                 global_cpu_usage_percentage: Some(5.0),
                 used_memory_percentage: Some(5.0),
+                connected_workstations: Some(0),
+                failed_login_attempts: Some(0),
+                oldest_event_age_days: Some(0),
+                event_log_archive: Some(EventLogArchive {
+                    days_since_last_archive: Some(1),
+                    last_archive_failed: false,
+                }),
+                blocked_sessions: Some(0),
+                long_running_requests: Some(0),
+                backups: Some(BackupHealth {
+                    state: BackupState::Readable,
+                    full_backup_age_hours: Some(1),
+                    differential_backup_age_hours: None,
+                    log_backup_age_hours: None,
+                }),
+                replicas: Some(vec![ReplicaState {
+                    replica: "SQL01".to_string(),
+                    role: "PRIMARY".to_string(),
+                    availability_mode: "SYNCHRONOUS_COMMIT".to_string(),
+                    synchronization_state: "SYNCHRONIZED".to_string(),
+                }]),
+                silenced_checks: Vec::new(),
                 maedic_health: MaedicHealth {
                     database_connection: DatabaseConnectionState::Healthy,
                     version_number: env!("CARGO_PKG_VERSION").to_string(),
@@ -154,6 +315,17 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn should_error_on_unreachable_database() {
+        assert!(!health_is_good(
+            &PWHealth {
+                maedic_health: MaedicHealth::unhealthy(),
+                ..Default::default()
+            },
+            &LimitSettings::default()
+        ));
+    }
+
     #[test]
     fn should_error_on_service_down() {
         assert!(!health_is_good(
@@ -176,6 +348,24 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn silenced_checks_do_not_affect_the_health() {
+        let health = PWHealth {
+            hi_queue_size: Some(1001),
+            global_cpu_usage_percentage: Some(99.0),
+            silenced_checks: vec![HealthCheck::HiQueueSize],
+            ..Default::default()
+        };
+        assert!(!health_is_good(&health, &LimitSettings::default()));
+        assert!(health_is_good(
+            &PWHealth {
+                silenced_checks: vec![HealthCheck::HiQueueSize, HealthCheck::CpuUsage],
+                ..health
+            },
+            &LimitSettings::default()
+        ));
+    }
+
     #[test]
     fn should_error_on_unhealthy_spool_files() {
         assert!(!health_is_good(
@@ -184,6 +374,7 @@ mod tests {
                     spool_file_count: 11,
                     description: "yeet".to_string(),
                     directory: "C:\\Yeet\\ProWatch".to_string(),
+                    panels: Vec::new(),
                 }]
                 .into(),
                 ..Default::default()
@@ -214,12 +405,179 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn should_error_on_too_few_connected_workstations() {
+        assert!(!health_is_good(
+            &PWHealth {
+                connected_workstations: Some(1),
+                ..Default::default()
+            },
+            &LimitSettings {
+                min_connected_workstations: 2,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn should_error_on_too_many_failed_logins() {
+        assert!(!health_is_good(
+            &PWHealth {
+                failed_login_attempts: Some(6),
+                ..Default::default()
+            },
+            &LimitSettings {
+                max_failed_login_attempts: 5,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn should_error_on_exceeded_event_log_retention() {
+        assert!(!health_is_good(
+            &PWHealth {
+                oldest_event_age_days: Some(400),
+                ..Default::default()
+            },
+            &LimitSettings {
+                max_event_log_age_days: 365,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[rstest]
+    #[case(Some(8), false)]
+    #[case(None, false)]
+    #[case(Some(1), true)]
+    fn should_error_on_an_outdated_or_failed_archive(
+        #[case] days_since_last_archive: Option<i32>,
+        #[case] last_archive_failed: bool,
+    ) {
+        assert!(!health_is_good(
+            &PWHealth {
+                event_log_archive: Some(EventLogArchive {
+                    days_since_last_archive,
+                    last_archive_failed,
+                }),
+                ..Default::default()
+            },
+            &LimitSettings {
+                max_days_since_last_archive: 7,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn should_error_on_blocked_sessions() {
+        assert!(!health_is_good(
+            &PWHealth {
+                blocked_sessions: Some(3),
+                ..Default::default()
+            },
+            &LimitSettings {
+                max_blocked_sessions: 2,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn should_error_on_long_running_requests() {
+        assert!(!health_is_good(
+            &PWHealth {
+                long_running_requests: Some(2),
+                ..Default::default()
+            },
+            &LimitSettings {
+                max_long_running_requests: 1,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn should_error_on_old_full_backup() {
+        assert!(!health_is_good(
+            &PWHealth {
+                backups: Some(BackupHealth {
+                    full_backup_age_hours: Some(25),
+                    ..PWHealth::default().backups.unwrap()
+                }),
+                ..Default::default()
+            },
+            &LimitSettings {
+                max_full_backup_age_hours: 24,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn should_error_on_missing_log_backup() {
+        assert!(!health_is_good(
+            &PWHealth::default(),
+            &LimitSettings {
+                max_log_backup_age_hours: 1,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn should_error_on_unreadable_backup_history() {
+        assert!(!health_is_good(
+            &PWHealth {
+                backups: Some(BackupHealth::permission_denied()),
+                ..Default::default()
+            },
+            &LimitSettings {
+                max_full_backup_age_hours: 24,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[rstest]
+    #[case("SYNCHRONOUS_COMMIT", "SYNCHRONIZING")]
+    #[case("SYNCHRONOUS_COMMIT", "NOT SYNCHRONIZING")]
+    #[case("ASYNCHRONOUS_COMMIT", "NOT SYNCHRONIZING")]
+    fn should_error_on_unsynchronized_replica(
+        #[case] availability_mode: &str,
+        #[case] synchronization_state: &str,
+    ) {
+        let mut replicas = PWHealth::default().replicas.unwrap();
+        replicas.push(ReplicaState {
+            replica: "SQL02".to_string(),
+            role: "SECONDARY".to_string(),
+            availability_mode: availability_mode.to_string(),
+            synchronization_state: synchronization_state.to_string(),
+        });
+        assert!(!health_is_good(
+            &PWHealth {
+                replicas: Some(replicas),
+                ..Default::default()
+            },
+            &LimitSettings::default()
+        ));
+    }
+
     #[rstest]
     #[case(PWHealth {unhealthy_spool_files: None, ..Default::default()})]
     #[case(PWHealth {hi_queue_size: None, ..Default::default()})]
     #[case(PWHealth {service_state: None, ..Default::default()})]
     #[case(PWHealth {global_cpu_usage_percentage: None, ..Default::default()})]
     #[case(PWHealth {used_memory_percentage: None, ..Default::default()})]
+    #[case(PWHealth {connected_workstations: None, ..Default::default()})]
+    #[case(PWHealth {failed_login_attempts: None, ..Default::default()})]
+    #[case(PWHealth {oldest_event_age_days: None, ..Default::default()})]
+    #[case(PWHealth {event_log_archive: None, ..Default::default()})]
+    #[case(PWHealth {blocked_sessions: None, ..Default::default()})]
+    #[case(PWHealth {long_running_requests: None, ..Default::default()})]
+    #[case(PWHealth {backups: None, ..Default::default()})]
+    #[case(PWHealth {replicas: None, ..Default::default()})]
     fn ignoring_any_health_checks_yields_healthy_results(#[case] health: PWHealth) {
         assert!(health_is_good(&health, &LimitSettings::default()));
     }
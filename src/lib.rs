@@ -1,10 +1,20 @@
+pub mod auth;
+pub mod capacity;
 pub mod checks;
+pub mod cli;
 pub mod configuration;
 pub mod database;
-pub(crate) mod error;
+pub mod error;
+pub(crate) mod failover;
 pub(crate) mod handler;
 pub mod health;
 pub mod metrics;
 pub(crate) mod model;
+pub mod nagios;
+pub mod reload;
 pub mod run;
+pub mod silence;
 pub mod telemetry;
+pub mod template;
+pub mod tls;
+pub mod validation;
@@ -1,58 +1,8 @@
-use maedic::{
-    configuration::get_settings,
-    database::setup_database_pool,
-    metrics::setup_metrics_registry,
-    run::{AppState, run},
-    telemetry::initialize_tracing,
-};
-use std::process::exit;
-
-use sysinfo::System;
+use clap::Parser;
+use maedic::cli::Cli;
+use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    if let Some(argument) = std::env::args().nth(1) {
-        if argument == "version" {
-            println!("maedic version: {}", env!("CARGO_PKG_VERSION"),);
-        } else {
-            println!(
-                "this application is configured via the 'base.yaml' file and the only other supported subcommand is 'version'"
-            );
-        }
-        exit(0);
-    }
-
-    let configuration = get_settings("base".to_string())?;
-
-    initialize_tracing(
-        configuration.application.log_level.clone(),
-        configuration.application.logfile_path.clone(),
-    )?;
-
-    let listener = tokio::net::TcpListener::bind(format!(
-        "{}:{}",
-        configuration.application.host, configuration.application.port
-    ))
-    .await
-    .expect("Could not bind to port");
-
-    let pool = setup_database_pool(configuration.database.clone())
-        .await
-        .expect("Could not establish database connection");
-    let (registry, metrics) = setup_metrics_registry().await;
-    let state = AppState {
-        pool,
-        settings: configuration.clone(),
-        sys: System::new_all(),
-        registry,
-        metrics,
-    };
-
-    run(listener, state, configuration)
-        .await
-        .expect("Failed to build application")
-        .await
-        .expect("Failed to start application");
-
-    Ok(())
+async fn main() -> ExitCode {
+    Cli::parse().run().await
 }
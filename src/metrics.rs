@@ -1,18 +1,35 @@
-use crate::configuration::DBConnectionPool;
+use crate::capacity::CapacityReport;
+use crate::configuration::{DBConnectionPool, MaintenanceWindow, MetricSettings};
 use crate::database::{
-    get_card_state, get_hiqueue_count_per_panel, get_panel_state, get_table_count,
-    get_unhealthy_spoolfiles, get_version_number,
+    get_access_events, get_audit_changes, get_backup_health, get_card_state, get_client_sessions,
+    get_database_files, get_database_sessions, get_event_log_retention, get_failed_logins,
+    get_hiqueue_count_per_panel, get_locked_operator_count, get_mssql_activity, get_mssql_instance,
+    get_panel_state, get_recent_event_count, get_replica_states, get_table_count,
+    get_unhealthy_spoolfiles, get_version_number, get_wait_stats, get_workstation_logins,
 };
 use crate::error::ApplicationError;
+use crate::model::access_event::{
+    AccessEventCount, AccessEventGrouping, AccessResult, limit_to_top_n,
+};
+use crate::model::backup::{BackupState, BackupType};
 use crate::model::card::CardStates;
+use crate::model::mssql::DatabaseFile;
 use crate::model::table::Tables;
+use crate::model::workstation::{SessionState, limit_sessions_to_top_n};
+use crate::silence::Silence;
+use chrono::{DateTime, Utc};
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
 use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{Histogram, exponential_buckets};
 use prometheus_client::registry::Registry;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
+use tracing::warn;
 
 /// `VersionComponents` lists the different components of a Version
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue, EnumIter)]
@@ -60,6 +77,124 @@ pub struct PanelInstalledLabel {
     pub minor_version: i64,
 }
 
+/// `AccessEventDeviceLabels` is the displayed label for the access events per logical device
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct AccessEventDeviceLabels {
+    pub device: String,
+    pub result: AccessResult,
+}
+
+/// `AccessEventPanelLabels` is the displayed label for the access events per panel
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct AccessEventPanelLabels {
+    pub panel: String,
+    pub result: AccessResult,
+}
+
+/// `ClientSessionLabels` is the displayed label for the PW client sessions
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ClientSessionLabels {
+    pub workstation: String,
+    pub state: SessionState,
+}
+
+/// `WorkstationLabel` is the displayed label for the workstation logins
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct WorkstationLabel {
+    pub workstation: String,
+}
+
+/// `DatabaseSessionLabels` is the displayed label for the connections to the SQL Server
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct DatabaseSessionLabels {
+    pub host: String,
+    pub program: String,
+}
+
+/// `OperatorLabel` is the displayed label for the failed operator logins
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct OperatorLabel {
+    pub operator: String,
+}
+
+/// `AuditChangeLabels` is the displayed label for the configuration changes from the audit log
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct AuditChangeLabels {
+    pub table: String,
+    pub operation: i32,
+    pub user: String,
+}
+
+/// `MssqlInfoLabels` is the displayed label for the SQL Server version
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct MssqlInfoLabels {
+    pub version: String,
+    pub edition: String,
+}
+
+/// `DatabaseStateLabel` is the displayed label for the state of the PW database
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct DatabaseStateLabel {
+    pub state: String,
+}
+
+/// `ReplicaRoleLabels` is the displayed label for the role of an availability group replica
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ReplicaRoleLabels {
+    pub replica: String,
+    pub role: String,
+}
+
+/// `ReplicaSynchronizationLabels` is the displayed label for the synchronization of an availability group replica
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ReplicaSynchronizationLabels {
+    pub replica: String,
+    pub availability_mode: String,
+    pub synchronization_state: String,
+}
+
+/// `DatabaseFileLabels` is the displayed label for the data and log files
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct DatabaseFileLabels {
+    pub file: String,
+    pub file_type: String,
+}
+
+/// `WaitTypeLabel` is the displayed label for the SQL Server wait stats
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct WaitTypeLabel {
+    pub wait_type: String,
+}
+
+/// `BackupTypeLabel` is the displayed label for the backup ages
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct BackupTypeLabel {
+    pub backup_type: BackupType,
+}
+
+/// `CapacityTableLabel` is the displayed label for the growth of the big tables
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct CapacityTableLabel {
+    pub table: String,
+}
+
+/// `QueryLabel` is the tracing span name of a database query
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct QueryLabel {
+    pub query: String,
+}
+
+/// Latency of the database queries per tracing span name
+pub type QueryDurations = Family<QueryLabel, Histogram, fn() -> Histogram>;
+
+/// Queries cancelled by the query timeout per tracing span name
+pub type QueryTimeouts = Family<QueryLabel, Counter>;
+
+/// Buckets from 5ms up to ~10s to cover everything up to the query timeout
+pub(crate) fn query_durations() -> QueryDurations {
+    Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.005, 2.0, 12)))
+}
+
 /// `MethodLabel` is the displayed label for the requests to maedic itself
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 pub struct EndpointLabels {
@@ -67,11 +202,51 @@ pub struct EndpointLabels {
 }
 
 /// The Endpoints of maedic
-#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, EncodeLabelValue, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Endpoint {
     Health,
     Metrics,
     Config,
+    Audit,
+    Capacity,
+    ConfigLimits,
+    Silences,
+}
+
+/// `ReloadResult` lists the outcomes of a configuration reload
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum ReloadResult {
+    Success,
+    Failure,
+}
+
+/// `ConfigReloadLabel` is the displayed label for the configuration reloads
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ConfigReloadLabel {
+    pub result: ReloadResult,
+}
+
+/// `MaintenanceWindowLabel` is the displayed label for the maintenance windows
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct MaintenanceWindowLabel {
+    pub window: String,
+}
+
+/// `SilenceLabels` are the displayed labels for the silences, the scopes are comma separated
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct SilenceLabels {
+    pub id: String,
+    pub created_by: String,
+    pub checks: String,
+    pub channels: String,
+    pub panels: String,
+}
+
+/// `TlsCertificateLabel` is the displayed label for the served certificate
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct TlsCertificateLabel {
+    pub certificate: String,
 }
 
 /// `Metrics` is the complete collection of all exposed metrics
@@ -83,7 +258,50 @@ pub struct Metrics {
     spool_files: Family<SpoolFileLabel, Gauge>,
     hi_queue_counts: Family<HiQueueLabel, Gauge>,
     panel_installed: Family<PanelInstalledLabel, Gauge>,
+    access_events_per_device: Family<AccessEventDeviceLabels, Gauge>,
+    access_events_per_panel: Family<AccessEventPanelLabels, Gauge>,
+    client_sessions: Family<ClientSessionLabels, Gauge>,
+    workstation_days_since_login: Family<WorkstationLabel, Gauge>,
+    workstations_inactive: Gauge,
+    database_sessions: Family<DatabaseSessionLabels, Gauge>,
+    failed_logins: Family<OperatorLabel, Gauge>,
+    locked_operator_accounts: Gauge,
+    audit_changes: Family<AuditChangeLabels, Gauge>,
+    event_log_oldest_event_age_days: Gauge,
+    event_log_events_last_day: Gauge,
+    event_log_days_since_last_archive: Gauge,
+    event_log_last_archive_failed: Gauge,
+    mssql_info: Family<MssqlInfoLabels, Gauge>,
+    mssql_uptime_seconds: Gauge,
+    mssql_database_state: Family<DatabaseStateLabel, Gauge>,
+    mssql_file_size_megabytes: Family<DatabaseFileLabels, Gauge>,
+    mssql_file_free_megabytes: Family<DatabaseFileLabels, Gauge>,
+    mssql_file_max_size_megabytes: Family<DatabaseFileLabels, Gauge>,
+    mssql_blocked_sessions: Gauge,
+    mssql_long_running_requests: Gauge,
+    mssql_wait_time_milliseconds: Family<WaitTypeLabel, Gauge>,
+    mssql_backup_age_hours: Family<BackupTypeLabel, Gauge>,
+    mssql_backup_permission_denied: Gauge,
+    mssql_replica_role: Family<ReplicaRoleLabels, Gauge>,
+    mssql_replica_synchronized: Family<ReplicaSynchronizationLabels, Gauge>,
+    capacity_growth_megabytes_per_day: Family<DatabaseFileLabels, Gauge<f64, AtomicU64>>,
+    capacity_days_until_full: Family<DatabaseFileLabels, Gauge<f64, AtomicU64>>,
+    capacity_table_growth_megabytes_per_day: Family<CapacityTableLabel, Gauge<f64, AtomicU64>>,
     maedic_requests: Family<EndpointLabels, Counter>,
+    auth_failures: Family<EndpointLabels, Counter>,
+    maedic_database_up: Gauge,
+    maedic_database_outages: Counter,
+    maedic_database_reconnect_attempts: Counter,
+    pool_connections: Gauge,
+    pool_idle_connections: Gauge,
+    pool_get_waited: Counter,
+    pool_get_wait_seconds: Counter<f64, AtomicU64>,
+    pool_get_timeouts: Counter,
+    config_reloads: Family<ConfigReloadLabel, Counter>,
+    config_restart_required: Gauge,
+    maintenance_window_active: Family<MaintenanceWindowLabel, Gauge>,
+    silence_expiry: Family<SilenceLabels, Gauge>,
+    tls_certificate_expiry_days: Family<TlsCertificateLabel, Gauge<f64, AtomicU64>>,
 }
 
 impl Metrics {
@@ -126,17 +344,234 @@ impl Metrics {
             })
             .set(installed.into());
     }
+
+    fn set_access_events(&self, grouping: &AccessEventGrouping, counts: Vec<AccessEventCount>) {
+        // Devices dropping out of the top-N must not keep their last value
+        match grouping {
+            AccessEventGrouping::LogicalDevice => self.access_events_per_device.clear(),
+            AccessEventGrouping::Panel => self.access_events_per_panel.clear(),
+        }
+        for count in counts {
+            for result in AccessResult::iter() {
+                let value = count.get(&result).into();
+                match grouping {
+                    AccessEventGrouping::LogicalDevice => self
+                        .access_events_per_device
+                        .get_or_create(&AccessEventDeviceLabels {
+                            device: count.description.clone(),
+                            result,
+                        })
+                        .set(value),
+                    AccessEventGrouping::Panel => self
+                        .access_events_per_panel
+                        .get_or_create(&AccessEventPanelLabels {
+                            panel: count.description.clone(),
+                            result,
+                        })
+                        .set(value),
+                };
+            }
+        }
+    }
+
+    fn set_client_sessions(&self, workstation: String, state: SessionState, value: i64) {
+        self.client_sessions
+            .get_or_create(&ClientSessionLabels { workstation, state })
+            .set(value);
+    }
+
+    fn set_workstation_days_since_login(&self, workstation: String, value: i64) {
+        self.workstation_days_since_login
+            .get_or_create(&WorkstationLabel { workstation })
+            .set(value);
+    }
+
+    fn set_database_sessions(&self, host: String, program: String, value: i64) {
+        self.database_sessions
+            .get_or_create(&DatabaseSessionLabels { host, program })
+            .set(value);
+    }
+
+    fn set_failed_logins(&self, operator: String, value: i64) {
+        self.failed_logins
+            .get_or_create(&OperatorLabel { operator })
+            .set(value);
+    }
+
+    fn set_audit_changes(&self, table: String, operation: i32, user: String, value: i64) {
+        self.audit_changes
+            .get_or_create(&AuditChangeLabels {
+                table,
+                operation,
+                user,
+            })
+            .set(value);
+    }
+
+    fn set_database_files(&self, files: Vec<DatabaseFile>) {
+        // A dropped or renamed file must not keep its last values
+        self.mssql_file_size_megabytes.clear();
+        self.mssql_file_free_megabytes.clear();
+        self.mssql_file_max_size_megabytes.clear();
+        for file in files {
+            let labels = DatabaseFileLabels {
+                file: file.name,
+                file_type: file.file_type,
+            };
+            self.mssql_file_size_megabytes
+                .get_or_create(&labels)
+                .set(file.size_megabytes.into());
+            self.mssql_file_free_megabytes
+                .get_or_create(&labels)
+                .set((file.size_megabytes - file.used_megabytes).into());
+            if let Some(max_size_megabytes) = file.max_size_megabytes {
+                self.mssql_file_max_size_megabytes
+                    .get_or_create(&labels)
+                    .set(max_size_megabytes.into());
+            }
+        }
+    }
+
+    pub(crate) fn set_capacity(&self, report: &CapacityReport) {
+        // Files or tables without a trend must not keep an outdated forecast
+        self.capacity_growth_megabytes_per_day.clear();
+        self.capacity_days_until_full.clear();
+        self.capacity_table_growth_megabytes_per_day.clear();
+        for file in &report.files {
+            let labels = DatabaseFileLabels {
+                file: file.name.clone(),
+                file_type: file.file_type.clone(),
+            };
+            if let Some(growth) = file.growth_megabytes_per_day {
+                self.capacity_growth_megabytes_per_day
+                    .get_or_create(&labels)
+                    .set(growth);
+            }
+            if let Some(days) = file.days_until_full {
+                self.capacity_days_until_full
+                    .get_or_create(&labels)
+                    .set(days);
+            }
+        }
+        for table in &report.tables {
+            if let Some(growth) = table.growth_megabytes_per_day {
+                self.capacity_table_growth_megabytes_per_day
+                    .get_or_create(&CapacityTableLabel {
+                        table: table.table_name.clone(),
+                    })
+                    .set(growth);
+            }
+        }
+    }
+
     pub(crate) fn inc_requests(&self, endpoint: Endpoint) {
         self.maedic_requests
             .get_or_create(&EndpointLabels { endpoint })
             .inc();
     }
+
+    pub(crate) fn inc_auth_failures(&self, endpoint: Endpoint) {
+        self.auth_failures
+            .get_or_create(&EndpointLabels { endpoint })
+            .inc();
+    }
+
+    pub(crate) fn inc_config_reloads(&self, result: ReloadResult) {
+        self.config_reloads
+            .get_or_create(&ConfigReloadLabel { result })
+            .inc();
+    }
+
+    pub(crate) fn set_config_restart_required(&self, settings: usize) {
+        self.config_restart_required.set(settings as i64);
+    }
+
+    /// Export whether each maintenance window is open and the expiry of each unexpired silence
+    pub(crate) fn set_silences(
+        &self,
+        windows: &[MaintenanceWindow],
+        silences: &[Silence],
+        now: DateTime<Utc>,
+    ) {
+        self.maintenance_window_active.clear();
+        for window in windows {
+            self.maintenance_window_active
+                .get_or_create(&MaintenanceWindowLabel {
+                    window: window.name.clone(),
+                })
+                .set(window.is_active(now.into()).into());
+        }
+        self.silence_expiry.clear();
+        for silence in silences.iter().filter(|silence| silence.expires_at > now) {
+            self.silence_expiry
+                .get_or_create(&SilenceLabels {
+                    id: silence.id.to_string(),
+                    created_by: silence.created_by.clone(),
+                    checks: silence
+                        .checks
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    channels: silence.channels.join(","),
+                    panels: silence.panels.join(","),
+                })
+                .set(silence.expires_at.timestamp());
+        }
+    }
+
+    pub(crate) fn set_tls_certificate_expiry(
+        &self,
+        certificate: &Path,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) {
+        self.tls_certificate_expiry_days
+            .get_or_create(&TlsCertificateLabel {
+                certificate: certificate.display().to_string(),
+            })
+            .set((expires_at - now).num_seconds() as f64 / 86400.0);
+    }
+
+    pub(crate) fn set_database_up(&self, up: bool) {
+        self.maedic_database_up.set(up.into());
+    }
+
+    pub(crate) fn inc_database_outages(&self) {
+        self.maedic_database_outages.inc();
+    }
+
+    pub(crate) fn inc_database_reconnect_attempts(&self) {
+        self.maedic_database_reconnect_attempts.inc();
+    }
+
+    pub(crate) fn set_pool_state(&self, state: bb8::State) {
+        self.pool_connections.set(state.connections.into());
+        self.pool_idle_connections
+            .set(state.idle_connections.into());
+        // bb8 only provides the totals, so the counters are advanced by the difference
+        let statistics = state.statistics;
+        self.pool_get_waited.inc_by(
+            statistics
+                .get_waited
+                .saturating_sub(self.pool_get_waited.get()),
+        );
+        self.pool_get_timeouts.inc_by(
+            statistics
+                .get_timed_out
+                .saturating_sub(self.pool_get_timeouts.get()),
+        );
+        self.pool_get_wait_seconds.inc_by(
+            (statistics.get_wait_time.as_secs_f64() - self.pool_get_wait_seconds.get()).max(0.0),
+        );
+    }
 }
 
 #[tracing::instrument(name = "Collect metrics", skip(pool, metrics))]
 pub(crate) async fn collect_metrics(
     pool: DBConnectionPool,
     metrics: &Metrics,
+    settings: &MetricSettings,
 ) -> Result<(), ApplicationError> {
     // Collect Version numbers
     let (major, minor, patch, build_no) = get_version_number(pool.clone()).await?;
@@ -183,10 +618,247 @@ pub(crate) async fn collect_metrics(
         );
     }
 
+    // Collect and set access events per logical device and panel
+    if settings.access_event_window_minutes != 0 && settings.access_event_top_n != 0 {
+        for grouping in AccessEventGrouping::iter() {
+            let access_events = get_access_events(
+                pool.clone(),
+                grouping.clone(),
+                settings.access_event_window_minutes,
+                &settings.access_event_types(),
+            )
+            .await?;
+            metrics.set_access_events(
+                &grouping,
+                limit_to_top_n(access_events, settings.access_event_top_n as usize),
+            );
+        }
+    }
+
+    // Collect and set PW client sessions and workstation logins
+    if settings.session_stale_minutes != 0 {
+        let client_sessions =
+            get_client_sessions(pool.clone(), settings.session_stale_minutes).await?;
+        // Sessions are removed on logout, so workstations must not keep their last value
+        metrics.client_sessions.clear();
+        for session in client_sessions {
+            metrics.set_client_sessions(
+                session.workstation.clone(),
+                SessionState::Active,
+                session.active.into(),
+            );
+            metrics.set_client_sessions(
+                session.workstation,
+                SessionState::Stale,
+                session.stale.into(),
+            );
+        }
+    }
+    if settings.workstation_inactive_days != 0 {
+        let workstation_logins = get_workstation_logins(pool.clone()).await?;
+        // Deleted or renamed workstations must not keep their last value
+        metrics.workstation_days_since_login.clear();
+        let mut inactive = 0;
+        for login in workstation_logins {
+            match login.days_since_login {
+                Some(days) => {
+                    if days >= settings.workstation_inactive_days {
+                        inactive += 1;
+                    }
+                    metrics.set_workstation_days_since_login(login.workstation, days.into());
+                }
+                None => inactive += 1,
+            }
+        }
+        metrics.workstations_inactive.set(inactive);
+    }
+
+    // Collect and set the connections to the SQL Server
+    if settings.database_session_top_n != 0 {
+        let database_sessions = get_database_sessions(pool.clone()).await?;
+        // Clients dropping out of the top-N must not keep their last value
+        metrics.database_sessions.clear();
+        for session in
+            limit_sessions_to_top_n(database_sessions, settings.database_session_top_n as usize)
+        {
+            metrics.set_database_sessions(
+                session.host_name,
+                session.program_name,
+                session.session_count.into(),
+            );
+        }
+    }
+
+    // Collect and set failed operator logins
+    if settings.failed_login_window_minutes != 0 {
+        let failed_logins =
+            get_failed_logins(pool.clone(), settings.failed_login_window_minutes).await?;
+        // Operators without failed logins within the window must not keep their last value
+        metrics.failed_logins.clear();
+        for failed_login in failed_logins {
+            metrics.set_failed_logins(failed_login.username, failed_login.failed_attempts.into());
+        }
+    }
+    let locked_operator_accounts = get_locked_operator_count(pool.clone()).await?;
+    metrics
+        .locked_operator_accounts
+        .set(locked_operator_accounts.into());
+
+    // Collect and set configuration changes from the audit log
+    if settings.audit_window_minutes != 0 {
+        let audit_changes = get_audit_changes(pool.clone(), settings.audit_window_minutes).await?;
+        // Changes leaving the window must not keep their last value
+        metrics.audit_changes.clear();
+        for change in audit_changes {
+            metrics.set_audit_changes(
+                change.table_name,
+                change.operation,
+                change.user_id,
+                change.change_count.into(),
+            );
+        }
+    }
+
+    // Collect and set the retention of the event log
+    let retention = get_event_log_retention(pool.clone()).await?;
+    metrics
+        .event_log_oldest_event_age_days
+        .set(retention.oldest_event_age_days.unwrap_or_default().into());
+    metrics
+        .event_log_events_last_day
+        .set(get_recent_event_count(pool.clone()).await?.into());
+    // Without any archive run the age is reported as -1
+    metrics.event_log_days_since_last_archive.set(
+        retention
+            .archive
+            .days_since_last_archive
+            .unwrap_or(-1)
+            .into(),
+    );
+    metrics
+        .event_log_last_archive_failed
+        .set(retention.archive.last_archive_failed.into());
+
+    // SQL Server instance health, missing permissions must not break the PW metrics
+    if let Err(err) = collect_mssql_metrics(pool.clone(), metrics, settings).await {
+        warn!("Could not collect SQL Server metrics: {:?}", err);
+    }
+    if let Err(err) = collect_backup_metrics(pool.clone(), metrics).await {
+        warn!("Could not collect SQL Server backup metrics: {:?}", err);
+    }
+    if let Err(err) = collect_replica_metrics(pool.clone(), metrics).await {
+        warn!("Could not collect SQL Server replica metrics: {:?}", err);
+    }
+
+    Ok(())
+}
+
+/// Collect the SQL Server instance health
+/// Most of these values require the `VIEW SERVER STATE` permission
+#[tracing::instrument(name = "Collect SQL Server metrics", skip(pool, metrics))]
+async fn collect_mssql_metrics(
+    pool: DBConnectionPool,
+    metrics: &Metrics,
+    settings: &MetricSettings,
+) -> Result<(), ApplicationError> {
+    let instance = get_mssql_instance(pool.clone()).await?;
+    metrics.mssql_info.clear();
+    metrics
+        .mssql_info
+        .get_or_create(&MssqlInfoLabels {
+            version: instance.version,
+            edition: instance.edition,
+        })
+        .set(1);
+    metrics
+        .mssql_uptime_seconds
+        .set(instance.uptime_seconds.into());
+    metrics.mssql_database_state.clear();
+    metrics
+        .mssql_database_state
+        .get_or_create(&DatabaseStateLabel {
+            state: instance.database_state,
+        })
+        .set(1);
+
+    metrics.set_database_files(get_database_files(pool.clone()).await?);
+
+    let activity = get_mssql_activity(pool.clone(), settings.long_running_request_seconds).await?;
+    metrics
+        .mssql_blocked_sessions
+        .set(activity.blocked_sessions.into());
+    metrics
+        .mssql_long_running_requests
+        .set(activity.long_running_requests.into());
+
+    if settings.wait_stats_top_n != 0 {
+        let wait_stats = get_wait_stats(pool.clone(), settings.wait_stats_top_n).await?;
+        metrics.mssql_wait_time_milliseconds.clear();
+        for wait_stat in wait_stats {
+            metrics
+                .mssql_wait_time_milliseconds
+                .get_or_create(&WaitTypeLabel {
+                    wait_type: wait_stat.wait_type,
+                })
+                .set(wait_stat.wait_time_milliseconds);
+        }
+    }
+
+    Ok(())
+}
+
+/// Collect the age of the last backups, reading the backup history requires access to `msdb`
+#[tracing::instrument(name = "Collect SQL Server backup metrics", skip(pool, metrics))]
+async fn collect_backup_metrics(
+    pool: DBConnectionPool,
+    metrics: &Metrics,
+) -> Result<(), ApplicationError> {
+    let backups = get_backup_health(pool.clone()).await?;
+    metrics
+        .mssql_backup_permission_denied
+        .set((backups.state == BackupState::PermissionDenied).into());
+    for backup_type in BackupType::iter() {
+        // Without any backup of this type the age is reported as -1
+        let age = backups.age_hours(&backup_type).unwrap_or(-1);
+        metrics
+            .mssql_backup_age_hours
+            .get_or_create(&BackupTypeLabel { backup_type })
+            .set(age.into());
+    }
     Ok(())
 }
 
-pub async fn setup_metrics_registry() -> (Registry, Metrics) {
+/// Collect the role and synchronization of the availability group replicas
+#[tracing::instrument(name = "Collect SQL Server replica metrics", skip(pool, metrics))]
+async fn collect_replica_metrics(
+    pool: DBConnectionPool,
+    metrics: &Metrics,
+) -> Result<(), ApplicationError> {
+    // Replicas can fail over or leave the availability group
+    let replicas = get_replica_states(pool.clone()).await?;
+    metrics.mssql_replica_role.clear();
+    metrics.mssql_replica_synchronized.clear();
+    for replica in replicas {
+        metrics
+            .mssql_replica_role
+            .get_or_create(&ReplicaRoleLabels {
+                replica: replica.replica.clone(),
+                role: replica.role.clone(),
+            })
+            .set(1);
+        metrics
+            .mssql_replica_synchronized
+            .get_or_create(&ReplicaSynchronizationLabels {
+                replica: replica.replica.clone(),
+                availability_mode: replica.availability_mode.clone(),
+                synchronization_state: replica.synchronization_state.clone(),
+            })
+            .set(replica.is_synchronized().into());
+    }
+    Ok(())
+}
+
+pub async fn setup_metrics_registry(pool: &DBConnectionPool) -> (Registry, Metrics) {
     let metrics = Metrics {
         table: Family::default(),
         status: Family::default(),
@@ -194,7 +866,50 @@ pub async fn setup_metrics_registry() -> (Registry, Metrics) {
         spool_files: Family::default(),
         hi_queue_counts: Family::default(),
         panel_installed: Family::default(),
+        access_events_per_device: Family::default(),
+        access_events_per_panel: Family::default(),
+        client_sessions: Family::default(),
+        workstation_days_since_login: Family::default(),
+        workstations_inactive: Gauge::default(),
+        database_sessions: Family::default(),
+        failed_logins: Family::default(),
+        locked_operator_accounts: Gauge::default(),
+        audit_changes: Family::default(),
+        event_log_oldest_event_age_days: Gauge::default(),
+        event_log_events_last_day: Gauge::default(),
+        event_log_days_since_last_archive: Gauge::default(),
+        event_log_last_archive_failed: Gauge::default(),
+        mssql_info: Family::default(),
+        mssql_uptime_seconds: Gauge::default(),
+        mssql_database_state: Family::default(),
+        mssql_file_size_megabytes: Family::default(),
+        mssql_file_free_megabytes: Family::default(),
+        mssql_file_max_size_megabytes: Family::default(),
+        mssql_blocked_sessions: Gauge::default(),
+        mssql_long_running_requests: Gauge::default(),
+        mssql_wait_time_milliseconds: Family::default(),
+        mssql_backup_age_hours: Family::default(),
+        mssql_backup_permission_denied: Gauge::default(),
+        mssql_replica_role: Family::default(),
+        mssql_replica_synchronized: Family::default(),
+        capacity_growth_megabytes_per_day: Family::default(),
+        capacity_days_until_full: Family::default(),
+        capacity_table_growth_megabytes_per_day: Family::default(),
         maedic_requests: Family::default(),
+        auth_failures: Family::default(),
+        maedic_database_up: Gauge::default(),
+        maedic_database_outages: Counter::default(),
+        maedic_database_reconnect_attempts: Counter::default(),
+        pool_connections: Gauge::default(),
+        pool_idle_connections: Gauge::default(),
+        pool_get_waited: Counter::default(),
+        pool_get_wait_seconds: Counter::default(),
+        pool_get_timeouts: Counter::default(),
+        config_reloads: Family::default(),
+        config_restart_required: Gauge::default(),
+        maintenance_window_active: Family::default(),
+        silence_expiry: Family::default(),
+        tls_certificate_expiry_days: Family::default(),
     };
     let mut registry = Registry::default();
     registry.register(
@@ -227,10 +942,235 @@ pub async fn setup_metrics_registry() -> (Registry, Metrics) {
         "Installation Status of each Panel, 1=UP, 0=DOWN",
         metrics.panel_installed.clone(),
     );
+    registry.register(
+        "maedic_access_events_per_device",
+        "Access events per logical device within the configured window",
+        metrics.access_events_per_device.clone(),
+    );
+    registry.register(
+        "maedic_access_events_per_panel",
+        "Access events per panel within the configured window",
+        metrics.access_events_per_panel.clone(),
+    );
+    registry.register(
+        "maedic_client_sessions",
+        "PW client sessions per Workstation",
+        metrics.client_sessions.clone(),
+    );
+    registry.register(
+        "maedic_workstation_days_since_login",
+        "Days since the last login of each Workstation",
+        metrics.workstation_days_since_login.clone(),
+    );
+    registry.register(
+        "maedic_workstations_inactive",
+        "Workstations without login within the configured days",
+        metrics.workstations_inactive.clone(),
+    );
+    registry.register(
+        "maedic_database_sessions",
+        "Connections to the SQL Server per Host and Program, limited to the top-N",
+        metrics.database_sessions.clone(),
+    );
+    registry.register(
+        "maedic_failed_logins",
+        "Failed logins per PW Operator within the configured window",
+        metrics.failed_logins.clone(),
+    );
+    registry.register(
+        "maedic_locked_operator_accounts",
+        "PW Operator accounts locked out after failed logins",
+        metrics.locked_operator_accounts.clone(),
+    );
+    registry.register(
+        "maedic_audit_changes",
+        "Configuration changes per Table, Operation and User within the configured window",
+        metrics.audit_changes.clone(),
+    );
+    registry.register(
+        "maedic_event_log_oldest_event_age_days",
+        "Age of the oldest Event in days",
+        metrics.event_log_oldest_event_age_days.clone(),
+    );
+    registry.register(
+        "maedic_event_log_events_last_day",
+        "Events recorded within the last 24 hours",
+        metrics.event_log_events_last_day.clone(),
+    );
+    registry.register(
+        "maedic_event_log_days_since_last_archive",
+        "Days since the last finished archive run, -1 if there was none",
+        metrics.event_log_days_since_last_archive.clone(),
+    );
+    registry.register(
+        "maedic_event_log_last_archive_failed",
+        "Failure of the last archive run, 1=FAILED, 0=OK",
+        metrics.event_log_last_archive_failed.clone(),
+    );
+    registry.register(
+        "maedic_mssql_info",
+        "Version and Edition of the SQL Server",
+        metrics.mssql_info.clone(),
+    );
+    registry.register(
+        "maedic_mssql_uptime_seconds",
+        "Uptime of the SQL Server",
+        metrics.mssql_uptime_seconds.clone(),
+    );
+    registry.register(
+        "maedic_mssql_database_state",
+        "State of the PW database, e.g. ONLINE or SUSPECT",
+        metrics.mssql_database_state.clone(),
+    );
+    registry.register(
+        "maedic_mssql_file_size_megabytes",
+        "Size of each data and log file",
+        metrics.mssql_file_size_megabytes.clone(),
+    );
+    registry.register(
+        "maedic_mssql_file_free_megabytes",
+        "Free space within each data and log file",
+        metrics.mssql_file_free_megabytes.clone(),
+    );
+    registry.register(
+        "maedic_mssql_file_max_size_megabytes",
+        "Maximum size of each data and log file, missing if unlimited",
+        metrics.mssql_file_max_size_megabytes.clone(),
+    );
+    registry.register(
+        "maedic_mssql_blocked_sessions",
+        "Sessions blocked by another session",
+        metrics.mssql_blocked_sessions.clone(),
+    );
+    registry.register(
+        "maedic_mssql_long_running_requests",
+        "Requests running longer than the configured seconds",
+        metrics.mssql_long_running_requests.clone(),
+    );
+    registry.register(
+        "maedic_mssql_wait_time_milliseconds",
+        "Accumulated wait time of the top wait types since the SQL Server start",
+        metrics.mssql_wait_time_milliseconds.clone(),
+    );
+    registry.register(
+        "maedic_mssql_backup_age_hours",
+        "Hours since the last backup of the PW database per type, -1 if there was none",
+        metrics.mssql_backup_age_hours.clone(),
+    );
+    registry.register(
+        "maedic_mssql_backup_permission_denied",
+        "Backup history in msdb is not readable, 1=DENIED, 0=OK",
+        metrics.mssql_backup_permission_denied.clone(),
+    );
+    registry.register(
+        "maedic_mssql_replica_role",
+        "Role of each availability group replica of the PW database",
+        metrics.mssql_replica_role.clone(),
+    );
+    registry.register(
+        "maedic_mssql_replica_synchronized",
+        "Whether each availability group replica of the PW database is synchronized",
+        metrics.mssql_replica_synchronized.clone(),
+    );
+    registry.register(
+        "maedic_capacity_growth_megabytes_per_day",
+        "Growth trend of each data and log file",
+        metrics.capacity_growth_megabytes_per_day.clone(),
+    );
+    registry.register(
+        "maedic_capacity_days_until_full",
+        "Projected days until each file reaches its maximum size or fills the disk",
+        metrics.capacity_days_until_full.clone(),
+    );
+    registry.register(
+        "maedic_capacity_table_growth_megabytes_per_day",
+        "Growth trend of the big PW tables",
+        metrics.capacity_table_growth_megabytes_per_day.clone(),
+    );
     registry.register(
         "maedic_requests_received",
         "Requests to maedic itself",
         metrics.maedic_requests.clone(),
     );
+    registry.register(
+        "maedic_auth_failures",
+        "Requests refused for missing or invalid credentials",
+        metrics.auth_failures.clone(),
+    );
+    registry.register(
+        "maedic_database_up",
+        "Whether the last connection check to the database succeeded",
+        metrics.maedic_database_up.clone(),
+    );
+    registry.register(
+        "maedic_database_outages",
+        "Times the database became unreachable",
+        metrics.maedic_database_outages.clone(),
+    );
+    registry.register(
+        "maedic_database_reconnect_attempts",
+        "Failed attempts to reconnect to the unreachable database",
+        metrics.maedic_database_reconnect_attempts.clone(),
+    );
+    registry.register(
+        "maedic_pool_connections",
+        "Connections currently held by the database pool",
+        metrics.pool_connections.clone(),
+    );
+    registry.register(
+        "maedic_pool_idle_connections",
+        "Idle connections of the database pool",
+        metrics.pool_idle_connections.clone(),
+    );
+    registry.register(
+        "maedic_pool_get_waited",
+        "Requests for a connection which had to wait for one",
+        metrics.pool_get_waited.clone(),
+    );
+    registry.register(
+        "maedic_pool_get_wait_seconds",
+        "Accumulated time spent waiting for a connection",
+        metrics.pool_get_wait_seconds.clone(),
+    );
+    registry.register(
+        "maedic_pool_get_timeouts",
+        "Requests for a connection which ran into the connection timeout",
+        metrics.pool_get_timeouts.clone(),
+    );
+    registry.register(
+        "maedic_config_reloads",
+        "Reloads of the configuration files per result",
+        metrics.config_reloads.clone(),
+    );
+    registry.register(
+        "maedic_config_restart_required",
+        "Changed settings which only take effect after a restart",
+        metrics.config_restart_required.clone(),
+    );
+    registry.register(
+        "maedic_maintenance_window_active",
+        "Maintenance windows per name, 1 while the window is open",
+        metrics.maintenance_window_active.clone(),
+    );
+    registry.register(
+        "maedic_silence_expiry_timestamp_seconds",
+        "Expiry of the unexpired silences as unix timestamp",
+        metrics.silence_expiry.clone(),
+    );
+    registry.register(
+        "maedic_tls_certificate_expiry_days",
+        "Days until the certificate served via HTTPS expires, negative once it expired",
+        metrics.tls_certificate_expiry_days.clone(),
+    );
+    registry.register(
+        "maedic_database_query_duration_seconds",
+        "Latency of the database queries per query",
+        pool.query_durations.clone(),
+    );
+    registry.register(
+        "maedic_database_query_timeouts",
+        "Database queries cancelled by the query timeout per query",
+        pool.query_timeouts.clone(),
+    );
     (registry, metrics)
 }
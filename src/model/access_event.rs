@@ -0,0 +1,155 @@
+use prometheus_client::encoding::EncodeLabelValue;
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+/// Label used for the bucket collecting every device, panel or host outside of the top-N
+pub(crate) const OTHER_BUCKET: &str = "other";
+
+/// `AccessEventGrouping` lists the `EV_LOG` columns the access events are grouped by
+#[derive(Clone, Debug, PartialEq, EnumIter, strum_macros::Display)]
+pub enum AccessEventGrouping {
+    #[strum(to_string = "LOGDEVDESCRP")]
+    LogicalDevice,
+    #[strum(to_string = "PANEL_DESCRP")]
+    Panel,
+}
+
+/// `AccessResult` lists the outcomes of an access attempt at a reader
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue, EnumIter)]
+pub enum AccessResult {
+    Granted,
+    Denied,
+    InvalidCard,
+}
+
+impl AccessResult {
+    /// The column counting the result in the access event query
+    pub(crate) fn column(&self) -> &'static str {
+        match self {
+            AccessResult::Granted => "granted",
+            AccessResult::Denied => "denied",
+            AccessResult::InvalidCard => "invalid_card",
+        }
+    }
+}
+
+/// Access events of a single logical device or panel within the configured window
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct AccessEventCount {
+    pub description: String,
+    pub granted: i32,
+    pub denied: i32,
+    pub invalid_card: i32,
+}
+
+impl AccessEventCount {
+    fn failed(&self) -> i32 {
+        self.denied + self.invalid_card
+    }
+
+    fn total(&self) -> i32 {
+        self.granted + self.failed()
+    }
+
+    pub(crate) fn get(&self, result: &AccessResult) -> i32 {
+        match result {
+            AccessResult::Granted => self.granted,
+            AccessResult::Denied => self.denied,
+            AccessResult::InvalidCard => self.invalid_card,
+        }
+    }
+}
+
+impl From<&tiberius::Row> for AccessEventCount {
+    fn from(val: &tiberius::Row) -> Self {
+        AccessEventCount {
+            description: val
+                .get::<&str, &str>("description")
+                .unwrap_or_default()
+                .to_string(),
+            granted: val.get("granted").unwrap_or_default(),
+            denied: val.get("denied").unwrap_or_default(),
+            invalid_card: val.get("invalid_card").unwrap_or_default(),
+        }
+    }
+}
+
+/// Keep the `top_n` entries with the most failed attempts and sum up the rest in an "other" bucket
+/// This keeps the label cardinality bounded on sites with thousands of readers
+pub(crate) fn limit_to_top_n(
+    mut counts: Vec<AccessEventCount>,
+    top_n: usize,
+) -> Vec<AccessEventCount> {
+    counts.sort_by(|a, b| {
+        b.failed()
+            .cmp(&a.failed())
+            .then(b.total().cmp(&a.total()))
+            .then(a.description.cmp(&b.description))
+    });
+    if counts.len() <= top_n {
+        return counts;
+    }
+    let other = counts.split_off(top_n).into_iter().fold(
+        AccessEventCount {
+            description: OTHER_BUCKET.to_string(),
+            granted: 0,
+            denied: 0,
+            invalid_card: 0,
+        },
+        |mut other, count| {
+            other.granted += count.granted;
+            other.denied += count.denied;
+            other.invalid_card += count.invalid_card;
+            other
+        },
+    );
+    counts.push(other);
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(description: &str, granted: i32, denied: i32, invalid_card: i32) -> AccessEventCount {
+        AccessEventCount {
+            description: description.to_string(),
+            granted,
+            denied,
+            invalid_card,
+        }
+    }
+
+    #[test]
+    fn small_lists_are_not_bucketed() {
+        let counts = vec![count("Door 1", 5, 0, 0), count("Door 2", 1, 1, 0)];
+        let limited = limit_to_top_n(counts, 5);
+        assert_eq!(limited.len(), 2);
+        assert!(limited.iter().all(|c| c.description != OTHER_BUCKET));
+    }
+
+    #[test]
+    fn readers_with_most_failures_are_kept() {
+        let counts = vec![
+            count("Busy Door", 500, 0, 0),
+            count("Broken Reader", 2, 40, 0),
+            count("Attacked Reader", 0, 3, 30),
+        ];
+        let limited = limit_to_top_n(counts, 2);
+        assert_eq!(limited[0].description, "Broken Reader");
+        assert_eq!(limited[1].description, "Attacked Reader");
+        assert_eq!(limited[2], count(OTHER_BUCKET, 500, 0, 0));
+    }
+
+    #[test]
+    fn other_bucket_sums_up_the_rest() {
+        let counts = vec![
+            count("Door 1", 1, 10, 0),
+            count("Door 2", 1, 1, 1),
+            count("Door 3", 2, 0, 1),
+        ];
+        let limited = limit_to_top_n(counts, 1);
+        assert_eq!(limited.len(), 2);
+        assert_eq!(limited[1], count(OTHER_BUCKET, 3, 1, 2));
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// Placeholder for the before and after images when they are redacted
+pub(crate) const REDACTED: &str = "[REDACTED]";
+
+/// Configuration changes from `AUDIT_LOG` within the configured window
+/// grouped by table, operation and user
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct AuditChangeCount {
+    pub table_name: String,
+    pub operation: i32,
+    pub user_id: String,
+    pub change_count: i32,
+}
+
+impl From<&tiberius::Row> for AuditChangeCount {
+    fn from(val: &tiberius::Row) -> Self {
+        AuditChangeCount {
+            table_name: val
+                .get::<&str, &str>("table_name")
+                .unwrap_or_default()
+                .to_string(),
+            operation: val.get("operation").unwrap_or_default(),
+            user_id: val
+                .get::<&str, &str>("user_id")
+                .unwrap_or_default()
+                .to_string(),
+            change_count: val.get("change_count").unwrap_or_default(),
+        }
+    }
+}
+
+/// A single changed column from `AUDIT_LOG`
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct AuditLogEntry {
+    pub timestamp: Option<String>,
+    pub table_name: String,
+    pub column_name: String,
+    pub key: Option<String>,
+    pub operation: Option<i32>,
+    pub user_id: Option<String>,
+    pub workstation: Option<String>,
+    pub before_image: Option<String>,
+    pub after_image: Option<String>,
+}
+
+impl From<&tiberius::Row> for AuditLogEntry {
+    fn from(val: &tiberius::Row) -> Self {
+        AuditLogEntry {
+            timestamp: val.get::<&str, &str>("timestamp").map(str::to_string),
+            table_name: val
+                .get::<&str, &str>("table_name")
+                .unwrap_or_default()
+                .to_string(),
+            column_name: val
+                .get::<&str, &str>("column_name")
+                .unwrap_or_default()
+                .to_string(),
+            key: val.get::<&str, &str>("key").map(str::to_string),
+            operation: val.get("operation"),
+            user_id: val.get::<&str, &str>("user_id").map(str::to_string),
+            workstation: val.get::<&str, &str>("workstation").map(str::to_string),
+            before_image: val.get::<&str, &str>("before_image").map(str::to_string),
+            after_image: val.get::<&str, &str>("after_image").map(str::to_string),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use prometheus_client::encoding::EncodeLabelValue;
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+/// `BackupType` lists the backup types recorded in `msdb.dbo.backupset`
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue, EnumIter)]
+pub enum BackupType {
+    Full,
+    Differential,
+    Log,
+}
+
+/// Whether the backup history in `msdb` could be read by the monitoring login
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum BackupState {
+    Readable,
+    PermissionDenied,
+}
+
+/// Hours since the last finished backup of the PW database per backup type
+/// Each age is `None` if there never was a backup of that type
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BackupHealth {
+    pub state: BackupState,
+    pub full_backup_age_hours: Option<i32>,
+    pub differential_backup_age_hours: Option<i32>,
+    pub log_backup_age_hours: Option<i32>,
+}
+
+impl BackupHealth {
+    pub(crate) fn permission_denied() -> Self {
+        Self {
+            state: BackupState::PermissionDenied,
+            full_backup_age_hours: None,
+            differential_backup_age_hours: None,
+            log_backup_age_hours: None,
+        }
+    }
+
+    pub(crate) fn age_hours(&self, backup_type: &BackupType) -> Option<i32> {
+        match backup_type {
+            BackupType::Full => self.full_backup_age_hours,
+            BackupType::Differential => self.differential_backup_age_hours,
+            BackupType::Log => self.log_backup_age_hours,
+        }
+    }
+}
+
+impl From<&tiberius::Row> for BackupHealth {
+    fn from(val: &tiberius::Row) -> Self {
+        BackupHealth {
+            state: BackupState::Readable,
+            full_backup_age_hours: val.get("full_backup_age_hours"),
+            differential_backup_age_hours: val.get("differential_backup_age_hours"),
+            log_backup_age_hours: val.get("log_backup_age_hours"),
+        }
+    }
+}
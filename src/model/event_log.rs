@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// Retention state of `EV_LOG` and the last archive run from `ARCH_TRACKING`
+/// The age is `None` if there are no events yet
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct EventLogRetention {
+    pub oldest_event_age_days: Option<i32>,
+    pub archive: EventLogArchive,
+}
+
+/// The last run of the event log archive
+/// `days_since_last_archive` is `None` if no archive run finished yet
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct EventLogArchive {
+    pub days_since_last_archive: Option<i32>,
+    pub last_archive_failed: bool,
+}
+
+impl From<&tiberius::Row> for EventLogRetention {
+    fn from(val: &tiberius::Row) -> Self {
+        EventLogRetention {
+            oldest_event_age_days: val.get("oldest_event_age_days"),
+            archive: EventLogArchive {
+                days_since_last_archive: val.get("days_since_last_archive"),
+                last_archive_failed: val
+                    .get::<i32, &str>("last_archive_failed")
+                    .unwrap_or_default()
+                    != 0,
+            },
+        }
+    }
+}
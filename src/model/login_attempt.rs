@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// Failed logins of a single PW operator from `UID_LOGIN_ATTEMPTS`
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct FailedLoginCount {
+    pub username: String,
+    pub failed_attempts: i32,
+}
+
+impl From<&tiberius::Row> for FailedLoginCount {
+    fn from(val: &tiberius::Row) -> Self {
+        FailedLoginCount {
+            username: val
+                .get::<&str, &str>("username")
+                .unwrap_or_default()
+                .to_string(),
+            failed_attempts: val.get("failed_attempts").unwrap_or_default(),
+        }
+    }
+}
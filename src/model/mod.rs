@@ -1,5 +1,12 @@
+pub(crate) mod access_event;
+pub(crate) mod audit_log;
+pub(crate) mod backup;
 pub(crate) mod card;
+pub(crate) mod event_log;
 pub(crate) mod hi_queue;
+pub(crate) mod login_attempt;
+pub(crate) mod mssql;
 pub(crate) mod panel;
 pub(crate) mod spoolfile;
 pub(crate) mod table;
+pub(crate) mod workstation;
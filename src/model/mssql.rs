@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+/// General information about the SQL Server instance and the PW database
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct MssqlInstance {
+    pub version: String,
+    pub edition: String,
+    pub uptime_seconds: i32,
+    pub database_state: String,
+}
+
+impl From<&tiberius::Row> for MssqlInstance {
+    fn from(val: &tiberius::Row) -> Self {
+        MssqlInstance {
+            version: val
+                .get::<&str, &str>("version")
+                .unwrap_or_default()
+                .to_string(),
+            edition: val
+                .get::<&str, &str>("edition")
+                .unwrap_or_default()
+                .to_string(),
+            uptime_seconds: val.get("uptime_seconds").unwrap_or_default(),
+            database_state: val
+                .get::<&str, &str>("database_state")
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+}
+
+/// Size and usage of a single data or log file of the PW database
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct DatabaseFile {
+    pub name: String,
+    pub file_type: String,
+    pub size_megabytes: i32,
+    pub used_megabytes: i32,
+    /// `None` if the file grows without limit
+    pub max_size_megabytes: Option<i32>,
+    /// Free space on the disk the file is located on
+    pub volume_available_megabytes: Option<i32>,
+}
+
+impl From<&tiberius::Row> for DatabaseFile {
+    fn from(val: &tiberius::Row) -> Self {
+        DatabaseFile {
+            name: val
+                .get::<&str, &str>("name")
+                .unwrap_or_default()
+                .to_string(),
+            file_type: val
+                .get::<&str, &str>("file_type")
+                .unwrap_or_default()
+                .to_string(),
+            size_megabytes: val.get("size_megabytes").unwrap_or_default(),
+            used_megabytes: val.get("used_megabytes").unwrap_or_default(),
+            max_size_megabytes: val.get("max_size_megabytes"),
+            volume_available_megabytes: val.get("volume_available_megabytes"),
+        }
+    }
+}
+
+/// Current load of the SQL Server
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct MssqlActivity {
+    pub blocked_sessions: i32,
+    pub long_running_requests: i32,
+}
+
+impl From<&tiberius::Row> for MssqlActivity {
+    fn from(val: &tiberius::Row) -> Self {
+        MssqlActivity {
+            blocked_sessions: val.get("blocked_sessions").unwrap_or_default(),
+            long_running_requests: val.get("long_running_requests").unwrap_or_default(),
+        }
+    }
+}
+
+/// Accumulated wait time of a single wait type since the start of the SQL Server
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct WaitStat {
+    pub wait_type: String,
+    pub wait_time_milliseconds: i64,
+}
+
+impl From<&tiberius::Row> for WaitStat {
+    fn from(val: &tiberius::Row) -> Self {
+        WaitStat {
+            wait_type: val
+                .get::<&str, &str>("wait_type")
+                .unwrap_or_default()
+                .to_string(),
+            wait_time_milliseconds: val.get("wait_time_milliseconds").unwrap_or_default(),
+        }
+    }
+}
+
+/// Reserved space of one of the big PW tables
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct TableSize {
+    pub table_name: String,
+    pub size_megabytes: i32,
+}
+
+impl From<&tiberius::Row> for TableSize {
+    fn from(val: &tiberius::Row) -> Self {
+        TableSize {
+            table_name: val
+                .get::<&str, &str>("table_name")
+                .unwrap_or_default()
+                .to_string(),
+            size_megabytes: val.get("size_megabytes").unwrap_or_default(),
+        }
+    }
+}
+
+/// Role and synchronization state of a single availability group replica of the PW database
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ReplicaState {
+    pub replica: String,
+    pub role: String,
+    pub availability_mode: String,
+    pub synchronization_state: String,
+}
+
+impl ReplicaState {
+    /// Asynchronous-commit replicas never get past `SYNCHRONIZING`, so that is good enough for them
+    pub(crate) fn is_synchronized(&self) -> bool {
+        match self.availability_mode.as_str() {
+            "ASYNCHRONOUS_COMMIT" => matches!(
+                self.synchronization_state.as_str(),
+                "SYNCHRONIZING" | "SYNCHRONIZED"
+            ),
+            _ => self.synchronization_state == "SYNCHRONIZED",
+        }
+    }
+}
+
+impl From<&tiberius::Row> for ReplicaState {
+    fn from(val: &tiberius::Row) -> Self {
+        ReplicaState {
+            replica: val
+                .get::<&str, &str>("replica")
+                .unwrap_or_default()
+                .to_string(),
+            role: val
+                .get::<&str, &str>("role")
+                .unwrap_or_default()
+                .to_string(),
+            availability_mode: val
+                .get::<&str, &str>("availability_mode")
+                .unwrap_or_default()
+                .to_string(),
+            synchronization_state: val
+                .get::<&str, &str>("synchronization_state")
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+}
@@ -6,6 +6,9 @@ pub struct SpoolFileCount {
     pub spool_file_count: i32,
     pub description: String,
     pub directory: String,
+    /// Descriptions of the panels on the channel
+    #[serde(default)]
+    pub panels: Vec<String>,
 }
 
 impl From<tiberius::Row> for SpoolFileCount {
@@ -14,6 +17,10 @@ impl From<tiberius::Row> for SpoolFileCount {
             description: val.get::<&str, &str>("description").unwrap().to_string(),
             spool_file_count: val.get("spool_file_count").unwrap(),
             directory: val.get::<&str, &str>("directory").unwrap().to_string(),
+            panels: val
+                .get::<&str, &str>("panel")
+                .map(|panel| vec![panel.to_string()])
+                .unwrap_or_default(),
         }
     }
 }
@@ -30,6 +37,10 @@ impl From<&tiberius::Row> for SpoolFileCount {
                 .get::<&str, &str>("directory")
                 .unwrap_or_default()
                 .to_string(),
+            panels: val
+                .get::<&str, &str>("panel")
+                .map(|panel| vec![panel.to_string()])
+                .unwrap_or_default(),
         }
     }
 }
@@ -0,0 +1,131 @@
+use prometheus_client::encoding::EncodeLabelValue;
+use serde::{Deserialize, Serialize};
+
+use crate::model::access_event::OTHER_BUCKET;
+
+/// `SessionState` distinguishes PW client sessions that are still updated from stale ones
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum SessionState {
+    Active,
+    Stale,
+}
+
+/// PW client sessions of a single workstation from the `SESSION` table
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct ClientSessionCount {
+    pub workstation: String,
+    pub active: i32,
+    pub stale: i32,
+}
+
+impl From<&tiberius::Row> for ClientSessionCount {
+    fn from(val: &tiberius::Row) -> Self {
+        ClientSessionCount {
+            workstation: val
+                .get::<&str, &str>("workstation")
+                .unwrap_or_default()
+                .to_string(),
+            active: val.get("active").unwrap_or_default(),
+            stale: val.get("stale").unwrap_or_default(),
+        }
+    }
+}
+
+/// Last login of a workstation as recorded in `WRKST.LASTLOGINTIMESTAMP`
+/// `days_since_login` is `None` if the workstation never logged in
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct WorkstationLogin {
+    pub workstation: String,
+    pub days_since_login: Option<i32>,
+}
+
+impl From<&tiberius::Row> for WorkstationLogin {
+    fn from(val: &tiberius::Row) -> Self {
+        WorkstationLogin {
+            workstation: val
+                .get::<&str, &str>("workstation")
+                .unwrap_or_default()
+                .to_string(),
+            days_since_login: val.get("days_since_login"),
+        }
+    }
+}
+
+/// Connections to the SQL Server grouped by client host and program
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct DatabaseSessionCount {
+    pub host_name: String,
+    pub program_name: String,
+    pub session_count: i32,
+}
+
+impl From<&tiberius::Row> for DatabaseSessionCount {
+    fn from(val: &tiberius::Row) -> Self {
+        DatabaseSessionCount {
+            host_name: val
+                .get::<&str, &str>("host_name")
+                .unwrap_or_default()
+                .to_string(),
+            program_name: val
+                .get::<&str, &str>("program_name")
+                .unwrap_or_default()
+                .to_string(),
+            session_count: val.get("session_count").unwrap_or_default(),
+        }
+    }
+}
+
+/// Keep the `top_n` hosts and programs with the most sessions and sum up the rest in an "other" bucket
+/// Clients like web servers or scripts may connect from countless hosts or with changing program names
+pub(crate) fn limit_sessions_to_top_n(
+    mut sessions: Vec<DatabaseSessionCount>,
+    top_n: usize,
+) -> Vec<DatabaseSessionCount> {
+    sessions.sort_by(|a, b| {
+        b.session_count
+            .cmp(&a.session_count)
+            .then(a.host_name.cmp(&b.host_name))
+            .then(a.program_name.cmp(&b.program_name))
+    });
+    if sessions.len() <= top_n {
+        return sessions;
+    }
+    let session_count = sessions
+        .split_off(top_n)
+        .iter()
+        .map(|session| session.session_count)
+        .sum();
+    sessions.push(DatabaseSessionCount {
+        host_name: OTHER_BUCKET.to_string(),
+        program_name: OTHER_BUCKET.to_string(),
+        session_count,
+    });
+    sessions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(host_name: &str, program_name: &str, session_count: i32) -> DatabaseSessionCount {
+        DatabaseSessionCount {
+            host_name: host_name.to_string(),
+            program_name: program_name.to_string(),
+            session_count,
+        }
+    }
+
+    #[test]
+    fn hosts_with_most_sessions_are_kept() {
+        let sessions = vec![
+            session("WS01", "ProWatch", 2),
+            session("PWSERVER", "micserver.exe", 12),
+            session("WEB01", "script 1", 1),
+            session("WEB02", "script 2", 3),
+        ];
+        let limited = limit_sessions_to_top_n(sessions, 2);
+        assert_eq!(limited[0], session("PWSERVER", "micserver.exe", 12));
+        assert_eq!(limited[1], session("WEB02", "script 2", 3));
+        assert_eq!(limited[2], session(OTHER_BUCKET, OTHER_BUCKET, 3));
+    }
+}
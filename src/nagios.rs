@@ -0,0 +1,231 @@
+use std::fmt::Display;
+
+use crate::{
+    configuration::LimitSettings,
+    health::{HealthCheck, PWHealth, failed_checks},
+};
+
+/// `NagiosState` lists the states of a Nagios plugin, each state is also its exit code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NagiosState {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl Display for NagiosState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NagiosState::Ok => write!(f, "OK"),
+            NagiosState::Warning => write!(f, "WARNING"),
+            NagiosState::Critical => write!(f, "CRITICAL"),
+            NagiosState::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+/// A single Nagios plugin output line
+pub fn plugin_output(state: NagiosState, summary: &str, perfdata: &[String]) -> String {
+    if perfdata.is_empty() {
+        format!("MAEDIC {state} - {summary}")
+    } else {
+        format!("MAEDIC {state} - {summary} | {}", perfdata.join(" "))
+    }
+}
+
+/// Evaluate the `PWHealth` like `health_is_good` and build the plugin output
+///
+/// Failed checks are CRITICAL, values reaching `warning_percentage` of their limit are a WARNING.
+/// Silenced checks are only listed in the summary.
+/// The perfdata covers the HI_QUEUE size, the spool files, CPU and RAM if their checks are active.
+pub(crate) fn nagios_report(
+    health: &PWHealth,
+    limits: &LimitSettings,
+    warning_percentage: f32,
+) -> (NagiosState, String) {
+    let warning_share = warning_percentage / 100.0;
+    let mut perfdata = Vec::new();
+    let mut warnings = Vec::new();
+
+    if let Some(hi_queue_size) = health.hi_queue_size {
+        let warning = (limits.hi_queue_count as f32 * warning_share) as i32;
+        perfdata.push(format!(
+            "hi_queue_size={hi_queue_size};{warning};{};0",
+            limits.hi_queue_count
+        ));
+        if hi_queue_size >= warning && !health.silenced_checks.contains(&HealthCheck::HiQueueSize) {
+            warnings.push(format!(
+                "hi_queue_size {hi_queue_size} of {}",
+                limits.hi_queue_count
+            ));
+        }
+    }
+    if let Some(unhealthy_spool_files) = &health.unhealthy_spool_files {
+        // Only channels above the limit are reported, so there is no warning level
+        perfdata.push(format!(
+            "unhealthy_spool_file_channels={};;0;0",
+            unhealthy_spool_files.len()
+        ));
+    }
+    for (label, usage, limit, check) in [
+        (
+            "cpu_usage",
+            health.global_cpu_usage_percentage,
+            limits.max_cpu_percentage,
+            HealthCheck::CpuUsage,
+        ),
+        (
+            "memory_usage",
+            health.used_memory_percentage,
+            limits.max_ram_percentage,
+            HealthCheck::MemoryUsage,
+        ),
+    ] {
+        if let Some(usage) = usage {
+            let warning = limit * warning_share;
+            perfdata.push(format!("{label}={usage}%;{warning};{limit};0;100"));
+            if usage >= warning && !health.silenced_checks.contains(&check) {
+                warnings.push(format!("{label} {usage}% of {limit}%"));
+            }
+        }
+    }
+
+    let (silenced, failed): (Vec<_>, Vec<_>) = failed_checks(health, limits)
+        .into_iter()
+        .partition(|check| health.silenced_checks.contains(check));
+    let (state, mut summary) = if !failed.is_empty() {
+        (
+            NagiosState::Critical,
+            format!(
+                "failed checks: {}",
+                failed
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )
+    } else if !warnings.is_empty() {
+        (
+            NagiosState::Warning,
+            format!("close to the limit: {}", warnings.join(", ")),
+        )
+    } else {
+        (NagiosState::Ok, "PW is healthy".to_string())
+    };
+    if !silenced.is_empty() {
+        summary.push_str(&format!(
+            ", silenced: {}",
+            silenced
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    (state, plugin_output(state, &summary, &perfdata))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        configuration::LimitSettings,
+        database::DatabaseConnectionState,
+        health::{HealthCheck, MaedicHealth, PWHealth},
+        model::spoolfile::SpoolFileCount,
+        nagios::{NagiosState, nagios_report},
+    };
+
+    fn health() -> PWHealth {
+        PWHealth {
+            maedic_health: MaedicHealth {
+                database_connection: DatabaseConnectionState::Healthy,
+                version_number: "1".to_string(),
+            },
+            service_state: None,
+            global_cpu_usage_percentage: Some(10.0),
+            used_memory_percentage: Some(20.0),
+            hi_queue_size: Some(100),
+            hi_queue_per_panel: None,
+            unhealthy_spool_files: Some(Vec::new()),
+            connected_workstations: None,
+            failed_login_attempts: None,
+            oldest_event_age_days: None,
+            event_log_archive: None,
+            blocked_sessions: None,
+            long_running_requests: None,
+            backups: None,
+            replicas: None,
+            silenced_checks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn healthy_is_ok_with_perfdata() {
+        let (state, output) = nagios_report(&health(), &LimitSettings::default(), 80.0);
+        assert_eq!(state, NagiosState::Ok);
+        assert_eq!(
+            output,
+            "MAEDIC OK - PW is healthy | hi_queue_size=100;800;1000;0 unhealthy_spool_file_channels=0;;0;0 cpu_usage=10%;64;80;0;100 memory_usage=20%;64;80;0;100"
+        );
+    }
+
+    #[test]
+    fn close_to_a_limit_is_a_warning() {
+        let health = PWHealth {
+            hi_queue_size: Some(900),
+            ..health()
+        };
+        let (state, output) = nagios_report(&health, &LimitSettings::default(), 80.0);
+        assert_eq!(state, NagiosState::Warning);
+        assert!(
+            output.starts_with("MAEDIC WARNING - close to the limit: hi_queue_size 900 of 1000 |")
+        );
+    }
+
+    #[test]
+    fn failed_checks_are_critical() {
+        let health = PWHealth {
+            hi_queue_size: Some(1200),
+            unhealthy_spool_files: Some(vec![SpoolFileCount {
+                spool_file_count: 20,
+                description: "Panel 1".to_string(),
+                directory: "C:\\spool\\panel1".to_string(),
+                panels: vec!["Panel 1".to_string()],
+            }]),
+            ..health()
+        };
+        let (state, output) = nagios_report(&health, &LimitSettings::default(), 80.0);
+        assert_eq!(state, NagiosState::Critical);
+        assert!(
+            output.starts_with("MAEDIC CRITICAL - failed checks: hi_queue_size, spool_files |")
+        );
+        assert!(output.contains("unhealthy_spool_file_channels=1;;0;0"));
+    }
+
+    #[test]
+    fn silenced_checks_are_ok() {
+        let health = PWHealth {
+            hi_queue_size: Some(1200),
+            silenced_checks: vec![HealthCheck::HiQueueSize],
+            ..health()
+        };
+        let (state, output) = nagios_report(&health, &LimitSettings::default(), 80.0);
+        assert_eq!(state, NagiosState::Ok);
+        assert!(output.starts_with("MAEDIC OK - PW is healthy, silenced: hi_queue_size |"));
+    }
+
+    #[test]
+    fn unreachable_database_is_critical() {
+        let health = PWHealth {
+            maedic_health: MaedicHealth::unhealthy(),
+            hi_queue_size: None,
+            unhealthy_spool_files: None,
+            ..health()
+        };
+        let (state, output) = nagios_report(&health, &LimitSettings::default(), 80.0);
+        assert_eq!(state, NagiosState::Critical);
+        assert!(output.starts_with("MAEDIC CRITICAL - failed checks: database_connection |"));
+    }
+}
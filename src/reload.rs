@@ -0,0 +1,270 @@
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use secrecy::ExposeSecret;
+use std::{collections::HashSet, path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::{Mutex, mpsc};
+use tracing::{error, info, warn};
+
+use crate::{
+    configuration::{ApplicationSettings, MetricSettings, Settings, load_settings_with_warnings},
+    metrics::ReloadResult,
+    run::AppState,
+    telemetry::LogLevelHandle,
+};
+
+/// Time to wait for further changes after a config file changed, editors often write a file in several steps
+pub(crate) const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Where the running configuration was loaded from
+#[derive(Clone, Debug, Default)]
+pub struct ConfigSource {
+    /// The layered config files, without files the configuration is never reloaded
+    pub files: Vec<PathBuf>,
+    /// Handle to apply a reloaded log level, `None` if tracing was set up elsewhere
+    pub log_level: Option<LogLevelHandle>,
+    /// Log level given on the command line, it takes precedence over the config files
+    pub log_level_override: Option<String>,
+}
+
+/// Field paths of the settings changed between `running` and `reloaded` which only take effect after a restart
+pub fn restart_required(running: &Settings, reloaded: &Settings) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    macro_rules! compare {
+        ($($section:ident . $field:ident),* $(,)?) => {
+            $(if running.$section.$field != reloaded.$section.$field {
+                changed.push(concat!(stringify!($section), ".", stringify!($field)));
+            })*
+        };
+    }
+    compare!(
+        application.port,
+        application.host,
+        application.logfile_path,
+        application.request_time_limit_seconds,
+        application.tls_certificate_file,
+        application.tls_key_file,
+        application.tls_client_ca_file,
+        application.tls_anonymous_endpoints,
+        database.hostname,
+        database.port,
+        database.auth_method,
+        database.username,
+        database.database_name,
+        database.trust_cert,
+        database.failover_partners,
+        database.multi_subnet_failover,
+        database.pool_max_size,
+        database.pool_min_idle,
+        database.connection_timeout_seconds,
+        database.idle_timeout_seconds,
+        database.max_lifetime_seconds,
+        database.query_timeout_seconds,
+        database.connection_check_interval_seconds,
+        database.reconnect_max_backoff_seconds,
+        metrics.capacity_sample_interval_minutes,
+        metrics.capacity_history_days,
+    );
+    if running.database.password.expose_secret() != reloaded.database.password.expose_secret() {
+        changed.push("database.password");
+    }
+    if running
+        .database
+        .connection_string
+        .as_ref()
+        .map(|secret| secret.expose_secret())
+        != reloaded
+            .database
+            .connection_string
+            .as_ref()
+            .map(|secret| secret.expose_secret())
+    {
+        changed.push("database.connection_string");
+    }
+    changed
+}
+
+/// The `reloaded` settings, keeping the values of `running` which need a restart to take effect
+fn reloadable_settings(running: &Settings, reloaded: Settings) -> Settings {
+    Settings {
+        application: ApplicationSettings {
+            port: running.application.port,
+            host: running.application.host.clone(),
+            logfile_path: running.application.logfile_path.clone(),
+            request_time_limit_seconds: running.application.request_time_limit_seconds,
+            tls_certificate_file: running.application.tls_certificate_file.clone(),
+            tls_key_file: running.application.tls_key_file.clone(),
+            tls_client_ca_file: running.application.tls_client_ca_file.clone(),
+            tls_anonymous_endpoints: running.application.tls_anonymous_endpoints.clone(),
+            ..reloaded.application
+        },
+        database: running.database.clone(),
+        limits: reloaded.limits,
+        metrics: MetricSettings {
+            capacity_sample_interval_minutes: running.metrics.capacity_sample_interval_minutes,
+            capacity_history_days: running.metrics.capacity_history_days,
+            ..reloaded.metrics
+        },
+        maintenance: reloaded.maintenance,
+        auth: reloaded.auth,
+    }
+}
+
+/// Re-read the config files and apply the limits, the log level and the enable flags at once
+///
+/// An invalid configuration is refused as a whole and the running settings are kept.
+/// Returns the changed settings which need a restart.
+pub async fn reload_configuration(
+    state: &Arc<Mutex<AppState>>,
+) -> anyhow::Result<Vec<&'static str>> {
+    let source = state.lock().await.config_source.clone();
+    let reloaded = load_settings_with_warnings(&source.files).map_err(anyhow::Error::from);
+    let reloaded = reloaded.and_then(|(mut settings, warnings)| {
+        warnings.log();
+        if let Some(log_level) = &source.log_level_override {
+            settings.application.log_level = log_level.clone();
+        }
+        settings.validate()?;
+        Ok(settings)
+    });
+    let mut state = state.lock().await;
+    let result = reloaded.and_then(|reloaded| {
+        if reloaded.application.log_level != state.settings.application.log_level
+            && let Some(log_level) = &source.log_level
+        {
+            log_level.set_log_level(&reloaded.application.log_level)?;
+        }
+        let restart_required = restart_required(&state.settings, &reloaded);
+        state.settings = reloadable_settings(&state.settings, reloaded);
+        Ok(restart_required)
+    });
+    match &result {
+        Ok(restart_required) => {
+            state.metrics.inc_config_reloads(ReloadResult::Success);
+            state
+                .metrics
+                .set_config_restart_required(restart_required.len());
+            if restart_required.is_empty() {
+                info!("Configuration reloaded");
+            } else {
+                warn!(
+                    "Configuration reloaded, changes to {} only take effect after a restart",
+                    restart_required.join(", ")
+                );
+            }
+        }
+        Err(err) => {
+            state.metrics.inc_config_reloads(ReloadResult::Failure);
+            error!("Configuration reload failed, keeping the running configuration: {err}");
+        }
+    }
+    result
+}
+
+/// Reload the configuration whenever one of the config files changes or, on unix, on SIGHUP
+///
+/// The watcher is set up right away, so no change after this call is missed.
+pub fn watch_configuration(state: Arc<Mutex<AppState>>, files: &[PathBuf]) {
+    let (sender, mut changes) = mpsc::unbounded_channel();
+    let watcher = watch_files(files, sender.clone());
+
+    #[cfg(unix)]
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(mut hangup) => {
+            tokio::spawn(async move {
+                while hangup.recv().await.is_some() {
+                    info!("Received SIGHUP");
+                    if sender.send(()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Err(err) => warn!("Could not listen for SIGHUP: {err}"),
+    }
+
+    tokio::spawn(async move {
+        // The watcher stops watching once it is dropped
+        let _watcher = watcher;
+        while changes.recv().await.is_some() {
+            tokio::time::sleep(DEBOUNCE).await;
+            while changes.try_recv().is_ok() {}
+            // The result is logged and exported as a metric
+            let _ = reload_configuration(&state).await;
+        }
+    });
+}
+
+/// Send on `sender` whenever one of the `files` changes, the watcher stops watching once it is dropped
+pub(crate) fn watch_files(
+    files: &[PathBuf],
+    sender: mpsc::UnboundedSender<()>,
+) -> Option<RecommendedWatcher> {
+    // Editors often replace a file instead of writing it, so the directories are watched
+    let watched_files: HashSet<PathBuf> = files
+        .iter()
+        .map(|file| file.canonicalize().unwrap_or_else(|_| file.clone()))
+        .collect();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event
+            && !event.kind.is_access()
+            && event.paths.iter().any(|path| watched_files.contains(path))
+        {
+            let _ = sender.send(());
+        }
+    });
+    match watcher {
+        Ok(mut watcher) => {
+            let directories: HashSet<PathBuf> = files
+                .iter()
+                .filter_map(|file| file.canonicalize().ok())
+                .filter_map(|file| file.parent().map(PathBuf::from))
+                .collect();
+            for directory in directories {
+                if let Err(err) = watcher.watch(&directory, RecursiveMode::NonRecursive) {
+                    warn!("Could not watch {} for changes: {err}", directory.display());
+                }
+            }
+            Some(watcher)
+        }
+        Err(err) => {
+            warn!("Could not watch {files:?} for changes: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        configuration::get_settings,
+        reload::{reloadable_settings, restart_required},
+    };
+
+    #[test]
+    fn unchanged_settings_need_no_restart() {
+        let settings = get_settings("test".to_string()).unwrap();
+        assert!(restart_required(&settings, &settings.clone()).is_empty());
+    }
+
+    #[test]
+    fn restart_only_settings_are_reported_and_kept() {
+        let running = get_settings("test".to_string()).unwrap();
+        let mut reloaded = running.clone();
+        reloaded.application.port = 4000;
+        reloaded.database.hostname = "SQLSERVER2".to_string();
+        reloaded.database.password = "changed".into();
+        reloaded.application.log_level = "debug".to_string();
+        reloaded.limits.hi_queue_count = 5;
+        reloaded.metrics.wait_stats_top_n = 3;
+
+        assert_eq!(
+            restart_required(&running, &reloaded),
+            vec!["application.port", "database.hostname", "database.password"]
+        );
+        let applied = reloadable_settings(&running, reloaded);
+        assert_eq!(applied.application.port, running.application.port);
+        assert_eq!(applied.database.hostname, running.database.hostname);
+        assert_eq!(applied.application.log_level, "debug");
+        assert_eq!(applied.limits.hi_queue_count, 5);
+        assert_eq!(applied.metrics.wait_stats_top_n, 3);
+    }
+}
@@ -1,22 +1,36 @@
 use crate::{
+    auth::VerifiedPasswords,
+    capacity::{CapacityHistory, sample_capacity},
     configuration::{DBConnectionPool, Settings},
+    database::{
+        DatabaseConnectionState, check_database_connection, monitor_database_connection,
+        setup_database_pool,
+    },
+    error::{ApplicationError, DatabaseSetupError},
     handler::{
-        check_health, get_config_handler, handle_timeout_error, handler_404, metrics_handler,
+        check_health, delete_silence_handler, get_audit_log_handler, get_capacity_handler,
+        get_config_handler, get_silences_handler, handle_timeout_error, handler_404,
+        metrics_handler, patch_limits_handler, post_silence_handler, put_limits_handler,
     },
-    metrics::Metrics,
+    metrics::{Endpoint, Metrics, setup_metrics_registry},
+    reload::{ConfigSource, watch_configuration},
+    silence::Silence,
+    tls::{ClientCertificate, TlsConfig, TlsListener, watch_certificates},
 };
+use axum::routing::{delete, get, put};
 use axum::{
-    Router,
+    Extension, Router,
     error_handling::HandleErrorLayer,
-    extract::{ConnectInfo, MatchedPath, Request, connect_info::IntoMakeServiceWithConnectInfo},
-    middleware::AddExtension,
+    extract::{ConnectInfo, MatchedPath, Request},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    serve::IncomingStream,
 };
-use axum::{routing::get, serve::Serve};
 use prometheus_client::registry::Registry;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{convert::Infallible, net::SocketAddr, pin::Pin, sync::Arc, time::Duration};
 use sysinfo::System;
 use tokio::{net::TcpListener, sync::Mutex};
-use tower::ServiceBuilder;
+use tower::{ServiceBuilder, service_fn};
 use tower_governor::{GovernorLayer, governor::GovernorConfigBuilder};
 use tower_http::trace::TraceLayer;
 use tracing::{info, info_span};
@@ -29,21 +43,55 @@ pub struct AppState {
     pub sys: System,
     pub registry: Registry,
     pub metrics: Metrics,
+    pub capacity: CapacityHistory,
+    /// Result of the last check of the database connection
+    pub database_connection: DatabaseConnectionState,
+    /// The config files the settings are reloaded from
+    pub config_source: ConfigSource,
+    /// Silences created via `/v1/silences`, they are lost on a restart
+    pub silences: Vec<Silence>,
+    /// The certificate of the HTTPS listener, `None` while serving plain HTTP
+    pub tls: Option<TlsConfig>,
+    /// Passwords of the `auth.users` verified before, shared with the requests checking them outside the lock
+    pub verified_passwords: VerifiedPasswords,
+}
+
+impl AppState {
+    /// Set up the connection pool and the metrics for the given `Settings`
+    /// The database does not need to be reachable, its state is checked once
+    pub async fn new(
+        settings: Settings,
+        config_source: ConfigSource,
+    ) -> Result<Self, DatabaseSetupError> {
+        let pool = setup_database_pool(settings.database.clone())?;
+        let database_connection = check_database_connection(pool.clone()).await;
+        let (registry, metrics) = setup_metrics_registry(&pool).await;
+        Ok(Self {
+            pool,
+            settings,
+            sys: System::new_all(),
+            registry,
+            metrics,
+            capacity: CapacityHistory::default(),
+            database_connection,
+            config_source,
+            silences: Vec::new(),
+            tls: None,
+            verified_passwords: VerifiedPasswords::default(),
+        })
+    }
 }
 
+/// The running server, it only completes if serving fails
+pub type Server = Pin<Box<dyn Future<Output = std::io::Result<()>> + Send>>;
+
 /// Start the Application with specific `Settings` and `AppState`
+/// With a `tls_certificate_file` the listener serves HTTPS instead of plain HTTP
 pub async fn run(
     listener: TcpListener,
-    state: AppState,
+    mut state: AppState,
     configuration: Settings,
-) -> Result<
-    Serve<
-        TcpListener,
-        IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
-        AddExtension<Router, ConnectInfo<SocketAddr>>,
-    >,
-    anyhow::Error,
-> {
+) -> Result<Server, anyhow::Error> {
     let governor_conf = GovernorConfigBuilder::default()
         //The combination of 1 + 4 leads to 5 successful requests before a rate limit response is hit
         .per_second(1)
@@ -51,12 +99,48 @@ pub async fn run(
         .finish()
         .expect("Failed to create RateLimiter Settings");
 
+    let tls = TlsConfig::from_settings(&configuration.application)?;
+    state.tls = tls.clone();
+
+    let config_files = state.config_source.files.clone();
+    let state = Arc::new(Mutex::new(state));
+
+    if !config_files.is_empty() {
+        watch_configuration(state.clone(), &config_files);
+    }
+
+    tokio::spawn(monitor_database_connection(
+        state.clone(),
+        Duration::from_secs(configuration.database.connection_check_interval_seconds),
+        Duration::from_secs(configuration.database.reconnect_max_backoff_seconds),
+    ));
+
+    let metric_settings = &configuration.metrics;
+    if metric_settings.capacity_sample_interval_minutes != 0 {
+        tokio::spawn(sample_capacity(
+            state.clone(),
+            Duration::from_secs(metric_settings.capacity_sample_interval_minutes as u64 * 60),
+            Duration::from_secs(metric_settings.capacity_history_days as u64 * 86400),
+        ));
+    }
+
     let app = Router::new()
         .route("/v1/health", get(check_health))
         .route("/v1/config", get(get_config_handler))
+        .route(
+            "/v1/config/limits",
+            put(put_limits_handler).patch(patch_limits_handler),
+        )
+        .route(
+            "/v1/silences",
+            get(get_silences_handler).post(post_silence_handler),
+        )
+        .route("/v1/silences/{id}", delete(delete_silence_handler))
+        .route("/v1/audit", get(get_audit_log_handler))
+        .route("/v1/capacity", get(get_capacity_handler))
         .route("/metrics", get(metrics_handler))
         .fallback(handler_404)
-        .with_state(Arc::new(Mutex::new(state)))
+        .with_state(state)
         .layer(
             ServiceBuilder::new()
                 .layer(HandleErrorLayer::new(handle_timeout_error))
@@ -88,8 +172,79 @@ pub async fn run(
         configuration
     );
 
-    Ok(axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
+    let Some(tls) = tls else {
+        return Ok(Box::pin(
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .into_future(),
+        ));
+    };
+    info!(
+        "Serving HTTPS with {}, it expires at {}",
+        tls.certificate_file().display(),
+        tls.expires_at()
+    );
+    watch_certificates(tls.clone());
+    let app = if configuration.application.tls_client_ca_file.is_empty() {
+        app
+    } else {
+        let anonymous_endpoints: Arc<[Endpoint]> =
+            configuration.application.tls_anonymous_endpoints.into();
+        app.layer(middleware::from_fn(move |request, next| {
+            require_client_certificate(anonymous_endpoints.clone(), request, next)
+        }))
+    };
+    // `into_make_service_with_connect_info` only knows the plain `TcpListener`
+    let make_service = service_fn(move |incoming: IncomingStream<'_, TlsListener>| {
+        let address = *incoming.remote_addr();
+        let client_certificate =
+            ClientCertificate(incoming.io().get_ref().1.peer_certificates().is_some());
+        std::future::ready(Ok::<_, Infallible>(
+            app.clone()
+                .layer(Extension(ConnectInfo(address)))
+                .layer(Extension(client_certificate)),
+        ))
+    });
+    Ok(Box::pin(
+        axum::serve(TlsListener::new(listener, tls)?, make_service).into_future(),
     ))
 }
+
+/// The `Endpoint` served on a route
+fn endpoint(path: &str) -> Option<Endpoint> {
+    match path {
+        "/v1/health" => Some(Endpoint::Health),
+        "/v1/config" => Some(Endpoint::Config),
+        "/v1/config/limits" => Some(Endpoint::ConfigLimits),
+        "/v1/silences" | "/v1/silences/{id}" => Some(Endpoint::Silences),
+        "/v1/audit" => Some(Endpoint::Audit),
+        "/v1/capacity" => Some(Endpoint::Capacity),
+        "/metrics" => Some(Endpoint::Metrics),
+        _ => None,
+    }
+}
+
+/// Refuse requests without a client certificate, unless their endpoint is one of the `anonymous_endpoints`
+/// A certificate not signed by one of the client CAs already fails the handshake
+async fn require_client_certificate(
+    anonymous_endpoints: Arc<[Endpoint]>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let verified = request
+        .extensions()
+        .get::<ClientCertificate>()
+        .is_some_and(|ClientCertificate(verified)| *verified);
+    let anonymous = request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|path| endpoint(path.as_str()))
+        .is_some_and(|endpoint| anonymous_endpoints.contains(&endpoint));
+    if verified || anonymous {
+        next.run(request).await
+    } else {
+        ApplicationError::ClientCertificateRequired.into_response()
+    }
+}
@@ -0,0 +1,436 @@
+use chrono::{DateTime, FixedOffset, Local, TimeDelta, Utc};
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::{
+    configuration::{LimitSettings, MaintenanceWindow},
+    health::{HealthCheck, PWHealth},
+};
+
+/// An ad-hoc silence created via `/v1/silences`, it is kept in memory until it expires
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Silence {
+    pub id: Uuid,
+    /// Silenced checks, none silences every check
+    pub checks: Vec<HealthCheck>,
+    /// Silenced channels of the `spool_files` check by their description
+    pub channels: Vec<String>,
+    /// Silenced panels by their description, covering the `spool_files` of their channels and their `hi_queue_size`
+    /// Without channels and panels every channel and panel is silenced
+    #[serde(default)]
+    pub panels: Vec<String>,
+    pub expires_at: DateTime<Utc>,
+    /// Name of the API token which created the silence
+    pub created_by: String,
+    pub comment: String,
+}
+
+/// The body of a request creating a `Silence`, either `duration_minutes` or `expires_at` is required
+#[derive(Deserialize, Debug)]
+pub struct NewSilence {
+    #[serde(default)]
+    pub checks: Vec<HealthCheck>,
+    #[serde(default)]
+    pub channels: Vec<String>,
+    #[serde(default)]
+    pub panels: Vec<String>,
+    pub duration_minutes: Option<u64>,
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub comment: String,
+}
+
+impl NewSilence {
+    /// The `Silence` created by `created_by` at `now`
+    pub(crate) fn into_silence(
+        self,
+        created_by: String,
+        now: DateTime<Utc>,
+    ) -> Result<Silence, String> {
+        let expires_at = match (self.duration_minutes, self.expires_at) {
+            (Some(duration_minutes), None) => i64::try_from(duration_minutes)
+                .ok()
+                .and_then(TimeDelta::try_minutes)
+                .and_then(|duration| now.checked_add_signed(duration))
+                .ok_or_else(|| format!("duration_minutes {duration_minutes} is too long"))?,
+            (None, Some(expires_at)) => expires_at,
+            _ => return Err("Either duration_minutes or expires_at is required".to_string()),
+        };
+        if expires_at <= now {
+            return Err(format!("expires_at {expires_at} is not in the future"));
+        }
+        Ok(Silence {
+            id: Uuid::new_v4(),
+            checks: self.checks,
+            channels: self.channels,
+            panels: self.panels,
+            expires_at,
+            created_by,
+            comment: self.comment,
+        })
+    }
+}
+
+pub(crate) fn parse_schedule(schedule: &str) -> Result<Cron, croner::errors::CronError> {
+    Cron::from_str(schedule)
+}
+
+pub(crate) fn parse_timestamp(
+    timestamp: &str,
+) -> Result<DateTime<FixedOffset>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(timestamp)
+}
+
+impl MaintenanceWindow {
+    /// Whether the window is open at `now`, an invalid window is never open
+    pub(crate) fn is_active(&self, now: DateTime<Local>) -> bool {
+        if let Some(schedule) = &self.schedule {
+            let duration = TimeDelta::try_minutes(self.duration_minutes as i64).unwrap_or_default();
+            parse_schedule(schedule)
+                .and_then(|cron| cron.find_previous_occurrence(&now, true))
+                .is_ok_and(|opened| now < opened + duration)
+        } else if let (Some(start), Some(end)) = (&self.start, &self.end) {
+            match (parse_timestamp(start), parse_timestamp(end)) {
+                (Ok(start), Ok(end)) => start <= now && now < end,
+                _ => false,
+            }
+        } else {
+            false
+        }
+    }
+}
+
+/// The checks, channels and panels covered by a maintenance window or a silence
+struct Scope<'a> {
+    checks: &'a [HealthCheck],
+    channels: &'a [String],
+    panels: &'a [String],
+}
+
+impl Scope<'_> {
+    /// Whether the scope covers the `check`, optionally for a single `channel` and its `panels`
+    /// A scope limited to `channels` or `panels` only covers the `spool_files` of those channels
+    /// and panels and the `hi_queue_size` of those panels
+    fn covers(&self, check: &HealthCheck, channel: Option<&str>, panels: &[String]) -> bool {
+        (self.checks.is_empty() || self.checks.contains(check))
+            && (self.channels.is_empty() && self.panels.is_empty()
+                || channel.is_some_and(|channel| self.channels.iter().any(|c| c == channel))
+                || panels.iter().any(|panel| self.panels.contains(panel)))
+    }
+}
+
+/// The `failed` checks covered by an open maintenance window or an unexpired silence
+///
+/// The `spool_files` check is only silenced if every unhealthy channel is covered, by itself or one of its panels.
+/// The `hi_queue_size` check is silenced once the entries of the uncovered panels are within the limit.
+pub(crate) fn silenced_checks(
+    health: &PWHealth,
+    failed: &[HealthCheck],
+    limits: &LimitSettings,
+    windows: &[MaintenanceWindow],
+    silences: &[Silence],
+    now: DateTime<Local>,
+) -> Vec<HealthCheck> {
+    let scopes: Vec<Scope> = windows
+        .iter()
+        .filter(|window| window.is_active(now))
+        .map(|window| Scope {
+            checks: &window.checks,
+            channels: &window.channels,
+            panels: &window.panels,
+        })
+        .chain(
+            silences
+                .iter()
+                .filter(|silence| silence.expires_at > now)
+                .map(|silence| Scope {
+                    checks: &silence.checks,
+                    channels: &silence.channels,
+                    panels: &silence.panels,
+                }),
+        )
+        .collect();
+    let covered = |check: &HealthCheck, channel: Option<&str>, panels: &[String]| {
+        scopes
+            .iter()
+            .any(|scope| scope.covers(check, channel, panels))
+    };
+    failed
+        .iter()
+        .filter(|check| {
+            match (
+                check,
+                &health.unhealthy_spool_files,
+                &health.hi_queue_per_panel,
+            ) {
+                (HealthCheck::SpoolFiles, Some(spool_files), _) => {
+                    spool_files.iter().all(|spool_file| {
+                        covered(check, Some(&spool_file.description), &spool_file.panels)
+                    })
+                }
+                (HealthCheck::HiQueueSize, _, Some(hi_queue_per_panel)) => {
+                    let silenced: i32 = hi_queue_per_panel
+                        .iter()
+                        .filter(|panel| {
+                            covered(check, None, std::slice::from_ref(&panel.description))
+                        })
+                        .map(|panel| panel.hi_queue_count)
+                        .sum();
+                    covered(check, None, &[])
+                        || health.hi_queue_size.unwrap_or_default() - silenced
+                            <= limits.hi_queue_count
+                }
+                _ => covered(check, None, &[]),
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Local, TimeDelta, TimeZone, Utc};
+    use rstest::rstest;
+
+    use crate::{
+        configuration::{LimitSettings, MaintenanceWindow},
+        health::{HealthCheck, PWHealth},
+        model::{hi_queue::HiQueueCount, spoolfile::SpoolFileCount},
+        silence::{NewSilence, Silence, silenced_checks},
+    };
+
+    fn spool_file(channel: &str, panels: Vec<&str>) -> SpoolFileCount {
+        SpoolFileCount {
+            spool_file_count: 20,
+            description: channel.to_string(),
+            directory: format!("C:\\spool\\{channel}"),
+            panels: panels.into_iter().map(str::to_string).collect(),
+        }
+    }
+
+    fn silence(
+        checks: Vec<HealthCheck>,
+        channels: Vec<&str>,
+        panels: Vec<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> Silence {
+        Silence {
+            id: uuid::Uuid::new_v4(),
+            checks,
+            channels: channels.into_iter().map(str::to_string).collect(),
+            panels: panels.into_iter().map(str::to_string).collect(),
+            expires_at,
+            created_by: "ops".to_string(),
+            comment: String::new(),
+        }
+    }
+
+    #[rstest]
+    // Saturday 22:30, within the 2h window opened at 22:00
+    #[case(Local.with_ymd_and_hms(2026, 10, 17, 22, 30, 0).unwrap(), true)]
+    // Sunday 00:30, the window closed at 00:00
+    #[case(Local.with_ymd_and_hms(2026, 10, 18, 0, 30, 0).unwrap(), false)]
+    // Friday 22:30
+    #[case(Local.with_ymd_and_hms(2026, 10, 16, 22, 30, 0).unwrap(), false)]
+    fn scheduled_windows_open_for_their_duration(
+        #[case] now: DateTime<Local>,
+        #[case] active: bool,
+    ) {
+        let window = MaintenanceWindow {
+            name: "patchday".to_string(),
+            schedule: Some("0 22 * * SAT".to_string()),
+            duration_minutes: 120,
+            ..MaintenanceWindow::default()
+        };
+        assert_eq!(window.is_active(now), active);
+    }
+
+    #[test]
+    fn one_off_windows_open_between_start_and_end() {
+        let window = MaintenanceWindow {
+            name: "upgrade".to_string(),
+            start: Some("2026-11-01T20:00:00+00:00".to_string()),
+            end: Some("2026-11-01T23:00:00+00:00".to_string()),
+            ..MaintenanceWindow::default()
+        };
+        let at = |hour| {
+            Utc.with_ymd_and_hms(2026, 11, 1, hour, 0, 0)
+                .unwrap()
+                .into()
+        };
+        assert!(!window.is_active(at(19)));
+        assert!(window.is_active(at(20)));
+        assert!(!window.is_active(at(23)));
+    }
+
+    #[test]
+    fn silences_cover_their_checks_until_they_expire() {
+        let now = Local::now();
+        let failed = vec![HealthCheck::HiQueueSize, HealthCheck::CpuUsage];
+        let silences = vec![
+            silence(
+                vec![HealthCheck::HiQueueSize],
+                vec![],
+                vec![],
+                (now + TimeDelta::minutes(5)).into(),
+            ),
+            silence(vec![], vec![], vec![], (now - TimeDelta::minutes(5)).into()),
+        ];
+        assert_eq!(
+            silenced_checks(
+                &PWHealth::default(),
+                &failed,
+                &LimitSettings::default(),
+                &[],
+                &silences,
+                now
+            ),
+            vec![HealthCheck::HiQueueSize]
+        );
+    }
+
+    #[test]
+    fn spool_files_are_silenced_once_every_channel_is_covered() {
+        let now = Local::now();
+        let expires_at = (now + TimeDelta::minutes(5)).into();
+        let health = PWHealth {
+            unhealthy_spool_files: Some(vec![
+                spool_file("Channel 1", vec!["Panel 1"]),
+                spool_file("Channel 2", vec!["Panel 2", "Panel 3"]),
+            ]),
+            ..PWHealth::default()
+        };
+        let failed = vec![HealthCheck::SpoolFiles, HealthCheck::HiQueueSize];
+        let limits = LimitSettings::default();
+        let channel_1 = silence(vec![], vec!["Channel 1"], vec![], expires_at);
+        assert!(
+            silenced_checks(
+                &health,
+                &failed,
+                &limits,
+                &[],
+                std::slice::from_ref(&channel_1),
+                now
+            )
+            .is_empty()
+        );
+        let channel_2 = silence(
+            vec![HealthCheck::SpoolFiles],
+            vec!["Channel 2"],
+            vec![],
+            expires_at,
+        );
+        assert_eq!(
+            silenced_checks(&health, &failed, &limits, &[], &[channel_1, channel_2], now),
+            vec![HealthCheck::SpoolFiles]
+        );
+    }
+
+    #[test]
+    fn panels_cover_the_spool_files_of_their_channels() {
+        let now = Local::now();
+        let health = PWHealth {
+            unhealthy_spool_files: Some(vec![
+                spool_file("Channel 1", vec!["Panel 1"]),
+                spool_file("Channel 2", vec!["Panel 2", "Panel 3"]),
+            ]),
+            ..PWHealth::default()
+        };
+        let failed = vec![HealthCheck::SpoolFiles];
+        let limits = LimitSettings::default();
+        let window = MaintenanceWindow {
+            name: "firmware rollout".to_string(),
+            start: Some((now - TimeDelta::minutes(5)).to_rfc3339()),
+            end: Some((now + TimeDelta::minutes(5)).to_rfc3339()),
+            panels: vec!["Panel 1".to_string(), "Panel 3".to_string()],
+            ..MaintenanceWindow::default()
+        };
+        assert_eq!(
+            silenced_checks(
+                &health,
+                &failed,
+                &limits,
+                std::slice::from_ref(&window),
+                &[],
+                now
+            ),
+            vec![HealthCheck::SpoolFiles]
+        );
+        let panel_1 = silence(
+            vec![],
+            vec![],
+            vec!["Panel 1"],
+            (now + TimeDelta::minutes(5)).into(),
+        );
+        assert!(silenced_checks(&health, &failed, &limits, &[], &[panel_1], now).is_empty());
+    }
+
+    #[test]
+    fn hi_queue_is_silenced_once_the_uncovered_panels_are_within_the_limit() {
+        let now = Local::now();
+        let expires_at = (now + TimeDelta::minutes(5)).into();
+        let hi_queue_count = |panel: &str, hi_queue_count| HiQueueCount {
+            hi_queue_count,
+            description: panel.to_string(),
+        };
+        let health = PWHealth {
+            hi_queue_size: Some(1500),
+            hi_queue_per_panel: Some(vec![
+                hi_queue_count("Panel 1", 1200),
+                hi_queue_count("Panel 2", 300),
+            ]),
+            ..PWHealth::default()
+        };
+        let failed = vec![HealthCheck::HiQueueSize];
+        let limits = LimitSettings {
+            hi_queue_count: 1000,
+            ..LimitSettings::default()
+        };
+        let panel_1 = silence(vec![], vec![], vec!["Panel 1"], expires_at);
+        assert_eq!(
+            silenced_checks(
+                &health,
+                &failed,
+                &limits,
+                &[],
+                std::slice::from_ref(&panel_1),
+                now
+            ),
+            vec![HealthCheck::HiQueueSize]
+        );
+        let panel_2 = silence(vec![], vec![], vec!["Panel 2"], expires_at);
+        assert!(silenced_checks(&health, &failed, &limits, &[], &[panel_2], now).is_empty());
+        // A channel scope does not cover the hi_queue
+        let channel_1 = silence(vec![], vec!["Channel 1"], vec![], expires_at);
+        assert!(silenced_checks(&health, &failed, &limits, &[], &[channel_1], now).is_empty());
+    }
+
+    #[test]
+    fn new_silences_need_an_expiry_in_the_future() {
+        let now = Utc::now();
+        let new_silence = |duration_minutes, expires_at| NewSilence {
+            checks: vec![],
+            channels: vec![],
+            panels: vec![],
+            duration_minutes,
+            expires_at,
+            comment: String::new(),
+        };
+        let silence = new_silence(Some(30), None)
+            .into_silence("ops".to_string(), now)
+            .unwrap();
+        assert_eq!(silence.expires_at, now + TimeDelta::minutes(30));
+        assert!(
+            new_silence(None, None)
+                .into_silence("ops".to_string(), now)
+                .is_err()
+        );
+        assert!(
+            new_silence(None, Some(now - TimeDelta::minutes(1)))
+                .into_silence("ops".to_string(), now)
+                .is_err()
+        );
+    }
+}
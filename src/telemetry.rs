@@ -1,23 +1,43 @@
 use std::str::FromStr;
 
+use tracing::Level;
 use tracing_subscriber::{
-    EnvFilter, Layer, filter::Directive, layer::SubscriberExt, util::SubscriberInitExt,
+    EnvFilter, Layer, Registry,
+    filter::{Directive, Targets},
+    layer::{Identity, SubscriberExt},
+    reload,
+    util::SubscriberInitExt,
 };
 
-/// Initialize `Tracing` and a `tracing_subscriber
-pub fn initialize_tracing(env_filter: String, path: String) -> anyhow::Result<()> {
-    let log_level: Directive = Directive::from_str(env_filter.as_str())?;
+/// Handle to change the log level of the running application
+#[derive(Clone, Debug)]
+pub struct LogLevelHandle(reload::Handle<EnvFilter, Registry>);
+
+impl LogLevelHandle {
+    /// Replace the log level of the standard output and the logfile
+    pub fn set_log_level(&self, log_level: &str) -> anyhow::Result<()> {
+        self.0.reload(log_filter(log_level)?)?;
+        Ok(())
+    }
+}
+
+/// The filter for the given log level, applied to the dependencies as well
+fn log_filter(env_filter: &str) -> anyhow::Result<EnvFilter> {
+    let log_level: Directive = Directive::from_str(env_filter)?;
     //Filter unnecessary info
-    let filter = EnvFilter::new(&env_filter)
+    Ok(EnvFilter::new(env_filter)
         .add_directive(format!("reqwest={}", log_level).parse()?)
-        .add_directive(format!("tiberius={}", log_level).parse()?);
+        .add_directive(format!("tiberius={}", log_level).parse()?))
+}
 
-    //Create a format layer with the appropriate filter for standard output
-    let fmt_layer = tracing_subscriber::fmt::layer()
-        .with_ansi(false)
-        .with_filter(filter.clone());
+/// Initialize `Tracing` and a `tracing_subscriber
+pub fn initialize_tracing(env_filter: String, path: String) -> anyhow::Result<LogLevelHandle> {
+    let (filter, handle) = reload::Layer::new(log_filter(&env_filter)?);
+
+    //Create a format layer for standard output
+    let fmt_layer = tracing_subscriber::fmt::layer().with_ansi(false);
 
-    //Create a format layer with the appropriate filter for logging to file
+    //Create a format layer for logging to file
     let file_log_layer = tracing_subscriber::fmt::layer()
         .with_ansi(false)
         .with_writer(
@@ -26,14 +46,34 @@ pub fn initialize_tracing(env_filter: String, path: String) -> anyhow::Result<()
                 .append(true)
                 .open(path)
                 .expect("Could not create or open logfile"),
-        )
-        .with_filter(filter);
+        );
+
+    //Keep the database query spans enabled at every log level, their names label the query latency metrics
+    let query_span_layer =
+        Identity::new().with_filter(Targets::new().with_target("maedic::database", Level::INFO));
+
+    tracing_subscriber::registry()
+        // install standard output layer and logging to file, sharing the reloadable filter
+        .with(fmt_layer.and_then(file_log_layer).with_filter(filter))
+        // keep the query spans
+        .with(query_span_layer)
+        .init();
+    Ok(LogLevelHandle(handle))
+}
+
+/// Initialize `Tracing` for the one-shot commands, logging to standard error to keep standard output clean
+pub fn initialize_command_tracing(env_filter: &str) -> anyhow::Result<()> {
+    let query_span_layer =
+        Identity::new().with_filter(Targets::new().with_target("maedic::database", Level::INFO));
 
     tracing_subscriber::registry()
-        // install standard output layer
-        .with(fmt_layer)
-        // install logging to file
-        .with(file_log_layer)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(std::io::stderr)
+                .with_filter(log_filter(env_filter)?),
+        )
+        .with(query_span_layer)
         .init();
     Ok(())
 }
@@ -0,0 +1,567 @@
+use config::{Config, ConfigError};
+use secrecy::ExposeSecret;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::configuration::{DatabaseSettings, LimitSettings, Settings};
+
+/// A section of the config file with its comment and its keys, each with the comment written behind it
+type Section = (
+    &'static str,
+    &'static str,
+    &'static [(&'static str, &'static str)],
+);
+
+/// The sections and keys in the order they are written
+/// Keys without a value, e.g. the optional `connection_string`, are written commented out
+const TEMPLATE: &[Section] = &[
+    (
+        "application",
+        "",
+        &[
+            ("port", ""),
+            ("host", "use 127.0.0.1 for local access only"),
+            ("logfile_path", ""),
+            (
+                "log_level",
+                "how detailed the log shall be, can be: debug, info, warn, error",
+            ),
+            ("service_name", "The name of the ProWatch Service"),
+            ("expose_config", "expose the configuration via /v1/config"),
+            (
+                "request_time_limit_seconds",
+                "Time limit for requests, increase if the database queries take too long",
+            ),
+            (
+                "audit_log_entries",
+                "no of recent audit log changes exposed via /v1/audit with all their columns, 0 deactivates the endpoint",
+            ),
+            (
+                "redact_audit_images",
+                "hide the before and after values of the audit log entries",
+            ),
+            (
+                "strict_config",
+                "refuse unknown or misspelled keys instead of logging a warning",
+            ),
+            (
+                "limits_file",
+                "the limits changed via /v1/config/limits are written to this file, relative to the config files, e.g. limits.yaml",
+            ),
+            (
+                "tls_certificate_file",
+                "PEM file with the certificate chain, serves HTTPS instead of HTTP and is reloaded when it changes",
+            ),
+            (
+                "tls_key_file",
+                "PEM file with the private key of the certificate",
+            ),
+            (
+                "tls_client_ca_file",
+                "PEM file with the CAs of the client certificates, every client then needs one (mTLS)",
+            ),
+            (
+                "tls_anonymous_endpoints",
+                "endpoints answered without a client certificate, e.g. [health] for load balancers",
+            ),
+        ],
+    ),
+    (
+        "database",
+        "",
+        &[
+            (
+                "hostname",
+                "also accepts an IP-Address or a named instance like SQLSERVER\\PROWATCH, whose port is resolved through the SQL Browser",
+            ),
+            ("port", ""),
+            (
+                "auth_method",
+                "Basic, Windows or Integrated, Integrated ignores the username and password",
+            ),
+            (
+                "username",
+                "required unless auth_method is Integrated or a connection_string is set",
+            ),
+            (
+                "password",
+                "or read it from env:<VARIABLE> or file:<path>, e.g. file:/run/secrets/db_password",
+            ),
+            ("database_name", ""),
+            (
+                "trust_cert",
+                "trust the certificate of the SQL Server without validating it",
+            ),
+            (
+                "connection_string",
+                "the ADO.NET connection string PW uses, replaces hostname, port, auth_method, username, password, database_name and trust_cert",
+            ),
+            (
+                "failover_partners",
+                "hosts with a replica of the database (AlwaysOn, mirroring) as host or host:port",
+            ),
+            (
+                "multi_subnet_failover",
+                "try all hosts at once instead of one after another",
+            ),
+            ("pool_max_size", "maximum no of connections to the database"),
+            (
+                "pool_min_idle",
+                "no of idle connections kept open, 0 keeps none",
+            ),
+            (
+                "connection_timeout_seconds",
+                "time to wait for a connection to the database",
+            ),
+            (
+                "idle_timeout_seconds",
+                "idle connections are closed after this time, 0 keeps them open",
+            ),
+            (
+                "max_lifetime_seconds",
+                "connections are closed after this time, 0 keeps them open",
+            ),
+            (
+                "query_timeout_seconds",
+                "time limit for each database query, 0 deactivates the limit",
+            ),
+            (
+                "connection_check_interval_seconds",
+                "interval between the checks of the database connection",
+            ),
+            (
+                "reconnect_max_backoff_seconds",
+                "maximum wait between reconnects to an unreachable database",
+            ),
+        ],
+    ),
+    (
+        "limits",
+        "set any value to 0 to deactivate the check",
+        &[
+            ("hi_queue_count", "max no of entries in the HI_QUEUE"),
+            (
+                "spool_file_count",
+                "max no of spool files acceptable for a healthy channel",
+            ),
+            ("max_cpu_percentage", ""),
+            ("max_ram_percentage", ""),
+            (
+                "check_local_service",
+                "check the state of the service_name on this machine",
+            ),
+            (
+                "min_connected_workstations",
+                "min no of workstations with an active PW client session",
+            ),
+            (
+                "max_failed_login_attempts",
+                "max no of failed operator logins within the failed_login_window_minutes",
+            ),
+            (
+                "max_event_log_age_days",
+                "max age of the oldest event in days, should match the archive retention policy",
+            ),
+            (
+                "max_days_since_last_archive",
+                "max days since the last event log archive, a failed archive run fails as well",
+            ),
+            (
+                "max_blocked_sessions",
+                "max no of blocked sessions on the SQL Server",
+            ),
+            (
+                "max_long_running_requests",
+                "max no of requests running longer than long_running_request_seconds",
+            ),
+            (
+                "max_full_backup_age_hours",
+                "max hours since the last full backup, requires read access to msdb",
+            ),
+            (
+                "max_differential_backup_age_hours",
+                "max hours since the last differential backup",
+            ),
+            (
+                "max_log_backup_age_hours",
+                "max hours since the last log backup",
+            ),
+            (
+                "check_replica_synchronization",
+                "fail if a replica of the database in an availability group is not synchronized",
+            ),
+        ],
+    ),
+    (
+        "metrics",
+        "set any value to 0 to deactivate the collection",
+        &[
+            (
+                "access_event_window_minutes",
+                "sliding window for the grant/deny statistics per reader",
+            ),
+            (
+                "access_event_top_n",
+                "readers/panels exported individually, all others are summed up as \"other\"",
+            ),
+            (
+                "granted_event_types",
+                "EV_LOG.EVNT_TYPE of granted accesses, without any event types the English event description is used",
+            ),
+            ("denied_event_types", "EV_LOG.EVNT_TYPE of denied accesses"),
+            (
+                "invalid_card_event_types",
+                "EV_LOG.EVNT_TYPE of invalid cards, they take precedence over denied and granted",
+            ),
+            (
+                "session_stale_minutes",
+                "PW client sessions without update for this long are considered stale",
+            ),
+            (
+                "alarm_workstations",
+                "names of the workstations counted by min_connected_workstations, none counts every workstation",
+            ),
+            (
+                "workstation_inactive_days",
+                "workstations without login for this long are considered inactive",
+            ),
+            (
+                "database_session_top_n",
+                "hosts/programs connected to the SQL Server exported individually, all others are summed up as \"other\"",
+            ),
+            (
+                "failed_login_window_minutes",
+                "sliding window for the failed operator logins, also used by max_failed_login_attempts",
+            ),
+            (
+                "audit_window_minutes",
+                "sliding window for the configuration changes from the audit log",
+            ),
+            (
+                "long_running_request_seconds",
+                "requests on the SQL Server running longer are considered long-running",
+            ),
+            (
+                "wait_stats_top_n",
+                "no of SQL Server wait types with the highest wait time exported",
+            ),
+            (
+                "capacity_sample_interval_minutes",
+                "interval between the samples for the database growth forecast",
+            ),
+            (
+                "capacity_history_days",
+                "days of samples used to fit the growth trend",
+            ),
+        ],
+    ),
+    (
+        "maintenance",
+        "failed checks in an open window are still shown, but do not affect the status code",
+        &[(
+            "windows",
+            "e.g. [{name: patchday, schedule: \"0 22 * * SAT\", duration_minutes: 120, checks: [spool_files], channels: [Channel 1]}, {name: firmware, schedule: \"0 6 * * SUN\", duration_minutes: 60, panels: [Panel 1]}, {name: upgrade, start: \"2026-11-01T20:00:00+01:00\", end: \"2026-11-01T23:00:00+01:00\"}]",
+        )],
+    ),
+    (
+        "auth",
+        "credentials for the API, changing the limits or silences requires one of them and is deactivated without any",
+        &[
+            (
+                "tokens",
+                "bearer tokens by the SHA-256 digest from maedic auth hash-token, e.g. [{name: prometheus, sha256: 9f86d081...}]",
+            ),
+            (
+                "users",
+                "basic auth users by the hash from maedic auth hash-password, e.g. [{name: ops, password_hash: \"$argon2id$v=19$...\"}]",
+            ),
+            (
+                "protected_endpoints",
+                "endpoints requiring credentials, can be: health, metrics, config, audit, capacity, silences, e.g. [metrics, config]",
+            ),
+        ],
+    ),
+];
+
+/// Keys renamed between versions as `(old, new)`, `config migrate` moves their values over
+pub const RENAMED_KEYS: &[(&str, &str)] = &[];
+
+/// The values of a config file by their path, e.g. `database.port`
+type Values = Map<String, Value>;
+
+/// The values of the `Default` settings, including the secrets which are never serialized
+fn default_values() -> Values {
+    let mut values = flatten(
+        serde_json::to_value(Settings::default()).expect("The default settings are serializable"),
+    );
+    let database = DatabaseSettings::default();
+    values.insert(
+        "database.password".to_string(),
+        Value::from(database.password.expose_secret()),
+    );
+    values.insert(
+        "database.connection_string".to_string(),
+        database
+            .connection_string
+            .map_or(Value::Null, |secret| Value::from(secret.expose_secret())),
+    );
+    values
+}
+
+/// Flatten the sections of a config file to their field paths
+fn flatten(config: Value) -> Values {
+    let mut values = Values::new();
+    if let Value::Object(sections) = config {
+        for (section, fields) in sections {
+            match fields {
+                Value::Object(fields) => {
+                    for (field, value) in fields {
+                        values.insert(format!("{section}.{field}"), value);
+                    }
+                }
+                value => {
+                    values.insert(section, value);
+                }
+            }
+        }
+    }
+    values
+}
+
+/// Write the `values` as a commented config file in the order of the `TEMPLATE`
+fn render(values: &Values) -> String {
+    let mut config = String::new();
+    for section in TEMPLATE {
+        render_section(&mut config, section, values);
+    }
+    config
+}
+
+fn render_section(
+    config: &mut String,
+    (section, section_comment, fields): &Section,
+    values: &Values,
+) {
+    config.push_str(section);
+    config.push(':');
+    push_comment(config, section_comment);
+    for (field, comment) in *fields {
+        match values.get(&format!("{section}.{field}")) {
+            Some(value) if !value.is_null() => {
+                config.push_str(&format!("  {field}: {value}"));
+            }
+            _ => config.push_str(&format!("  # {field}:")),
+        }
+        push_comment(config, comment);
+    }
+}
+
+fn push_comment(config: &mut String, comment: &str) {
+    if !comment.is_empty() {
+        config.push_str(" # ");
+        config.push_str(comment);
+    }
+    config.push('\n');
+}
+
+/// The commented default configuration, generated from the `Default` settings
+pub fn default_config() -> String {
+    render(&default_values())
+}
+
+/// The `limits` section alone, as written to the `application.limits_file`
+/// Only the `changed` limits are written, all others are commented out to keep them from the config files
+pub fn limits_config(limits: &LimitSettings, changed: &BTreeSet<String>) -> String {
+    let mut values = flatten(serde_json::json!({ "limits": limits }));
+    values.retain(|key, _| {
+        key.strip_prefix("limits.")
+            .is_some_and(|limit| changed.contains(limit))
+    });
+    let mut config =
+        "# Written by maedic, the limits changed via /v1/config/limits override the config files\n"
+            .to_string();
+    for section in TEMPLATE
+        .iter()
+        .filter(|(section, _, _)| *section == "limits")
+    {
+        render_section(&mut config, section, &values);
+    }
+    config
+}
+
+/// The result of upgrading an older config file to the current version
+#[derive(Debug, PartialEq)]
+pub struct Migration {
+    /// The upgraded config file
+    pub config: String,
+    /// Keys missing in the old file, written with their default value
+    pub added: Vec<String>,
+    /// Keys moved to their new name as `(old, new)`
+    pub renamed: Vec<(String, String)>,
+    /// Keys of the old file which are no longer known and were dropped
+    pub removed: Vec<String>,
+}
+
+/// Upgrade the config file at `path`, keeping its values and adding the missing keys with their defaults
+///
+/// The values are taken as written, secrets like `env:<VARIABLE>` are not resolved.
+/// The comments are regenerated from the template.
+pub fn migrate_config(path: &Path) -> Result<Migration, ConfigError> {
+    let old = Config::builder()
+        .add_source(config::File::from(path).required(true))
+        .build()?
+        .try_deserialize::<Value>()?;
+    Ok(migrate_values(flatten(old), RENAMED_KEYS))
+}
+
+fn migrate_values(mut old: Values, renamed_keys: &[(&str, &str)]) -> Migration {
+    let mut renamed = Vec::new();
+    for (old_key, new_key) in renamed_keys {
+        if !old.contains_key(*new_key)
+            && let Some(value) = old.remove(*old_key)
+        {
+            old.insert(new_key.to_string(), value);
+            renamed.push((old_key.to_string(), new_key.to_string()));
+        }
+    }
+
+    let defaults = default_values();
+    let mut values = Values::new();
+    let mut added = Vec::new();
+    for (key, default) in defaults.iter() {
+        match old.remove(key) {
+            Some(value) => {
+                values.insert(key.clone(), value);
+            }
+            None => {
+                // Optional keys without a default are not worth a report
+                if !default.is_null() {
+                    added.push(key.clone());
+                }
+                values.insert(key.clone(), default.clone());
+            }
+        }
+    }
+    Migration {
+        config: render(&values),
+        added,
+        renamed,
+        removed: old.into_iter().map(|(key, _)| key).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+    use serde_json::Value;
+    use std::collections::BTreeSet;
+
+    use crate::{
+        configuration::{LimitSettings, Settings, load_settings},
+        template::{
+            TEMPLATE, default_config, default_values, flatten, limits_config, migrate_values,
+        },
+    };
+
+    fn load(config: &str) -> Settings {
+        let path =
+            std::env::temp_dir().join(format!("maedic-template-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, config).unwrap();
+        let settings = load_settings(std::slice::from_ref(&path));
+        std::fs::remove_file(&path).unwrap();
+        settings.unwrap()
+    }
+
+    #[test]
+    fn the_template_covers_every_setting() {
+        let template: BTreeSet<String> = TEMPLATE
+            .iter()
+            .flat_map(|(section, _, fields)| {
+                fields
+                    .iter()
+                    .map(move |(field, _)| format!("{section}.{field}"))
+            })
+            .collect();
+        let settings: BTreeSet<String> = default_values().keys().cloned().collect();
+        assert_eq!(template, settings);
+    }
+
+    #[test]
+    fn the_default_config_loads_the_default_settings() {
+        let settings = load(&default_config());
+        assert_eq!(
+            serde_json::to_value(&settings).unwrap(),
+            serde_json::to_value(Settings::default()).unwrap()
+        );
+        // There is no default login, the credentials have to be filled in
+        let issues: Vec<String> = settings
+            .validate()
+            .unwrap_err()
+            .0
+            .into_iter()
+            .map(|issue| issue.field)
+            .collect();
+        assert_eq!(issues, vec!["database.username", "database.password"]);
+        assert!(settings.database.password.expose_secret().is_empty());
+        assert!(settings.database.connection_string.is_none());
+    }
+
+    #[test]
+    fn the_limits_config_overrides_the_limits() {
+        let limits = LimitSettings {
+            hi_queue_count: 5,
+            max_cpu_percentage: 95.5,
+            ..LimitSettings::default()
+        };
+        let changed = BTreeSet::from([
+            "hi_queue_count".to_string(),
+            "max_cpu_percentage".to_string(),
+        ]);
+        let config = limits_config(&limits, &changed);
+        assert!(config.starts_with("# Written by maedic"));
+        assert!(config.contains("\n  hi_queue_count: 5 # "));
+        // Unchanged limits are left to the config files
+        assert!(config.contains("\n  # spool_file_count:"));
+        assert_eq!(load(&config).limits, limits);
+    }
+
+    #[test]
+    fn migration_adds_missing_and_reports_unknown_keys() {
+        let mut old = flatten(serde_json::to_value(Settings::default()).unwrap());
+        old.insert(
+            "database.password".to_string(),
+            Value::from("env:DB_PASSWORD"),
+        );
+        old.insert("limits.hi_queue_count".to_string(), Value::from(5));
+        old.remove("database.trust_cert");
+        old.insert("database.trust_certs".to_string(), Value::from(true));
+        old.remove("metrics.wait_stats_top_n");
+        old.insert("metrics.wait_types_top_n".to_string(), Value::from(3));
+
+        let migration = migrate_values(
+            old,
+            &[("metrics.wait_types_top_n", "metrics.wait_stats_top_n")],
+        );
+        assert_eq!(migration.added, vec!["database.trust_cert"]);
+        assert_eq!(
+            migration.renamed,
+            vec![(
+                "metrics.wait_types_top_n".to_string(),
+                "metrics.wait_stats_top_n".to_string()
+            )]
+        );
+        assert_eq!(migration.removed, vec!["database.trust_certs"]);
+        assert!(migration.config.contains("  password: \"env:DB_PASSWORD\""));
+
+        // Keep the password literal so it loads without the environment variable
+        let settings = load(
+            &migration
+                .config
+                .replace("\"env:DB_PASSWORD\"", "\"Charlie 13\""),
+        );
+        assert_eq!(settings.limits.hi_queue_count, 5);
+        assert_eq!(settings.metrics.wait_stats_top_n, 3);
+        assert!(settings.database.trust_cert);
+    }
+}
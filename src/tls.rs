@@ -0,0 +1,299 @@
+use axum::serve::Listener;
+use chrono::{DateTime, Utc};
+use rustls::{
+    RootCertStore, ServerConfig,
+    crypto::ring::default_provider,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::WebPkiClientVerifier,
+};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+use tracing::{debug, error, info};
+
+use crate::{
+    configuration::ApplicationSettings,
+    error::TlsSetupError,
+    reload::{DEBOUNCE, watch_files},
+};
+
+/// Time a client gets to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The certificate, key and client CAs of the HTTPS listener, reloaded whenever their files change
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    certificate_file: PathBuf,
+    key_file: PathBuf,
+    client_ca_file: Option<PathBuf>,
+    loaded: Arc<RwLock<LoadedTls>>,
+}
+
+/// The `ServerConfig` built from the current content of the files
+#[derive(Debug)]
+struct LoadedTls {
+    server_config: Arc<ServerConfig>,
+    expires_at: DateTime<Utc>,
+}
+
+impl TlsConfig {
+    /// Load the configured PEM files, `None` without a `tls_certificate_file`
+    pub fn from_settings(application: &ApplicationSettings) -> Result<Option<Self>, TlsSetupError> {
+        if application.tls_certificate_file.is_empty() {
+            return Ok(None);
+        }
+        let certificate_file = PathBuf::from(&application.tls_certificate_file);
+        let key_file = PathBuf::from(&application.tls_key_file);
+        let client_ca_file = Some(&application.tls_client_ca_file)
+            .filter(|file| !file.is_empty())
+            .map(PathBuf::from);
+        let loaded = load(&certificate_file, &key_file, client_ca_file.as_deref())?;
+        Ok(Some(Self {
+            certificate_file,
+            key_file,
+            client_ca_file,
+            loaded: Arc::new(RwLock::new(loaded)),
+        }))
+    }
+
+    /// Re-read the PEM files, the running configuration is kept if they are invalid
+    pub(crate) fn reload(&self) -> Result<(), TlsSetupError> {
+        let loaded = load(
+            &self.certificate_file,
+            &self.key_file,
+            self.client_ca_file.as_deref(),
+        )?;
+        *self.loaded.write().unwrap_or_else(PoisonError::into_inner) = loaded;
+        Ok(())
+    }
+
+    /// The end of the validity of the served certificate
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.loaded
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .expires_at
+    }
+
+    /// The path of the served certificate, exported as the label of its expiry
+    pub fn certificate_file(&self) -> &Path {
+        &self.certificate_file
+    }
+
+    fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(
+            self.loaded
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .server_config
+                .clone(),
+        )
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        [&self.certificate_file, &self.key_file]
+            .into_iter()
+            .chain(&self.client_ca_file)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Whether the client of a connection presented a certificate signed by one of the client CAs
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ClientCertificate(pub(crate) bool);
+
+/// Build the `ServerConfig`, client certificates are verified if there is a `client_ca_file`
+/// A client without one still completes the handshake, the `tls_anonymous_endpoints` are checked per request
+fn load(
+    certificate_file: &Path,
+    key_file: &Path,
+    client_ca_file: Option<&Path>,
+) -> Result<LoadedTls, TlsSetupError> {
+    let certificates = load_certificates(certificate_file)?;
+    let key = PrivateKeyDer::from_pem_file(key_file).map_err(|err| invalid_pem(key_file, err))?;
+    let expires_at = expiry(certificate_file, &certificates[0])?;
+
+    let provider = Arc::new(default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match client_ca_file {
+        Some(client_ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in load_certificates(client_ca_file)? {
+                roots.add(certificate)?;
+            }
+            builder.with_client_cert_verifier(
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .allow_unauthenticated()
+                    .build()?,
+            )
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut server_config = builder.with_single_cert(certificates, key)?;
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(LoadedTls {
+        server_config: Arc::new(server_config),
+        expires_at,
+    })
+}
+
+/// Every certificate of a PEM file, at least one is required
+fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsSetupError> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| invalid_pem(path, err))?;
+    if certificates.is_empty() {
+        return Err(invalid_pem(path, "no certificate found"));
+    }
+    Ok(certificates)
+}
+
+fn invalid_pem(path: &Path, message: impl ToString) -> TlsSetupError {
+    TlsSetupError::InvalidPemFile {
+        path: path.display().to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// The end of the validity of a certificate
+fn expiry(path: &Path, certificate: &CertificateDer) -> Result<DateTime<Utc>, TlsSetupError> {
+    let invalid_certificate = |message: String| TlsSetupError::InvalidCertificate {
+        path: path.display().to_string(),
+        message,
+    };
+    let (_, certificate) = x509_parser::parse_x509_certificate(certificate)
+        .map_err(|err| invalid_certificate(err.to_string()))?;
+    DateTime::from_timestamp(certificate.validity().not_after.timestamp(), 0)
+        .ok_or_else(|| invalid_certificate("the expiry is out of range".to_string()))
+}
+
+/// Reload the certificate whenever one of its files changes, e.g. after a renewal
+pub(crate) fn watch_certificates(tls: TlsConfig) {
+    let (sender, mut changes) = mpsc::unbounded_channel();
+    let watcher = watch_files(&tls.files(), sender);
+    tokio::spawn(async move {
+        // The watcher stops watching once it is dropped
+        let _watcher = watcher;
+        while changes.recv().await.is_some() {
+            // The certificate and the key are often replaced one after the other
+            tokio::time::sleep(DEBOUNCE).await;
+            while changes.try_recv().is_ok() {}
+            match tls.reload() {
+                Ok(()) => info!(
+                    "TLS certificate reloaded, it expires at {}",
+                    tls.expires_at()
+                ),
+                Err(err) => {
+                    error!("TLS certificate reload failed, keeping the running certificate: {err}")
+                }
+            }
+        }
+    });
+}
+
+/// A `Listener` serving HTTPS, the handshakes run in the background so a slow client never blocks the others
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub(crate) fn new(listener: TcpListener, tls: TlsConfig) -> std::io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (sender, connections) = mpsc::channel(64);
+        tokio::spawn(accept_connections(listener, tls, sender));
+        Ok(Self {
+            local_addr,
+            connections,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // Accepting only stops once the listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Accept TCP connections and hand them over once their TLS handshake completed
+async fn accept_connections(
+    listener: TcpListener,
+    tls: TlsConfig,
+    sender: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    loop {
+        let (stream, address) = tokio::select! {
+            () = sender.closed() => break,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    // e.g. too many open files, retrying at once would only spin
+                    error!("Could not accept a connection: {err}");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+        };
+        let acceptor = tls.acceptor();
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let _ = sender.send((stream, address)).await;
+                }
+                Ok(Err(err)) => debug!("TLS handshake with {address} failed: {err}"),
+                Err(_) => debug!("TLS handshake with {address} timed out"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{CertificateParams, KeyPair, date_time_ymd};
+
+    use crate::{configuration::ApplicationSettings, tls::TlsConfig};
+
+    #[test]
+    fn the_expiry_is_read_from_the_certificate() {
+        let directory = std::env::temp_dir().join(format!("maedic-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&directory).unwrap();
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params.not_after = date_time_ymd(2031, 3, 1);
+        let certificate = params.self_signed(&key).unwrap();
+        std::fs::write(directory.join("cert.pem"), certificate.pem()).unwrap();
+        std::fs::write(directory.join("key.pem"), key.serialize_pem()).unwrap();
+
+        let mut application = ApplicationSettings::default();
+        assert!(TlsConfig::from_settings(&application).unwrap().is_none());
+        application.tls_certificate_file = directory.join("cert.pem").display().to_string();
+        application.tls_key_file = directory.join("missing.pem").display().to_string();
+        assert!(TlsConfig::from_settings(&application).is_err());
+        application.tls_key_file = directory.join("key.pem").display().to_string();
+        let tls = TlsConfig::from_settings(&application).unwrap().unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(tls.expires_at().to_rfc3339(), "2031-03-01T00:00:00+00:00");
+    }
+}
@@ -0,0 +1,718 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use argon2::PasswordHash;
+use secrecy::ExposeSecret;
+use serde::Serialize;
+use tracing_subscriber::filter::{Directive, LevelFilter};
+
+use crate::{
+    configuration::{DBAuthMethod, Settings},
+    database::parse_failover_partner,
+    silence::{parse_schedule, parse_timestamp},
+};
+
+/// A single problem found in the `Settings`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ValidationIssue {
+    /// Path of the offending field, e.g. `limits.hi_queue_count`
+    pub field: String,
+    pub message: String,
+    /// How to fix the problem
+    pub hint: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.field, self.message, self.hint)
+    }
+}
+
+/// All problems found in the `Settings`
+#[derive(thiserror::Error, Debug)]
+#[error("Invalid configuration:\n{}", .0.iter().map(|issue| format!("  {issue}")).collect::<Vec<_>>().join("\n"))]
+pub struct InvalidSettings(pub Vec<ValidationIssue>);
+
+/// A log level like `info` or a filter like `info,maedic=debug`
+/// A bare word is only accepted as a level, `Directive` would take it for a target and silently log nothing
+fn is_valid_log_level(log_level: &str) -> bool {
+    !log_level.is_empty()
+        && log_level.split(',').all(|directive| {
+            if directive.contains('=') {
+                Directive::from_str(directive).is_ok()
+            } else {
+                LevelFilter::from_str(directive).is_ok()
+            }
+        })
+}
+
+/// Collects the `ValidationIssue`s of a validation pass
+#[derive(Default)]
+struct Validator {
+    issues: Vec<ValidationIssue>,
+}
+
+impl Validator {
+    fn issue(&mut self, field: &str, message: impl Into<String>, hint: impl Into<String>) {
+        self.issues.push(ValidationIssue {
+            field: field.to_string(),
+            message: message.into(),
+            hint: hint.into(),
+        });
+    }
+
+    /// Counts and limits where 0 deactivates the check or collection
+    fn not_negative(&mut self, field: &str, value: i32, hint: &str) {
+        if value < 0 {
+            self.issue(field, format!("must not be negative, got {value}"), hint);
+        }
+    }
+
+    fn percentage(&mut self, field: &str, value: f32) {
+        if !(0.0..=100.0).contains(&value) {
+            self.issue(
+                field,
+                format!("must be a percentage between 0 and 100, got {value}"),
+                "set it to 0 to deactivate the check",
+            );
+        }
+    }
+
+    fn positive(&mut self, field: &str, value: u64, hint: &str) {
+        if value == 0 {
+            self.issue(field, "must be greater than 0", hint);
+        }
+    }
+}
+
+impl Settings {
+    /// Check the `Settings` for values that would fail at runtime, collecting every problem
+    pub fn validate(&self) -> Result<(), InvalidSettings> {
+        let mut validator = Validator::default();
+        self.validate_application(&mut validator);
+        self.validate_database(&mut validator);
+        self.validate_limits(&mut validator);
+        self.validate_metrics(&mut validator);
+        self.validate_maintenance(&mut validator);
+        self.validate_auth(&mut validator);
+        if validator.issues.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidSettings(validator.issues))
+        }
+    }
+
+    fn validate_application(&self, validator: &mut Validator) {
+        let application = &self.application;
+        if application.host.is_empty() {
+            validator.issue(
+                "application.host",
+                "must not be empty",
+                "use 0.0.0.0 to listen on all interfaces or 127.0.0.1 for local access only",
+            );
+        }
+        if !is_valid_log_level(&application.log_level) {
+            validator.issue(
+                "application.log_level",
+                format!("{:?} is not a valid log level", application.log_level),
+                "use one of trace, debug, info, warn, error",
+            );
+        }
+        if let Some(directory) = Path::new(&application.logfile_path).parent()
+            && !directory.as_os_str().is_empty()
+            && !directory.is_dir()
+        {
+            validator.issue(
+                "application.logfile_path",
+                format!("directory {} does not exist", directory.display()),
+                "create the directory or point the logfile_path to an existing one",
+            );
+        }
+        if self.limits.check_local_service && application.service_name.is_empty() {
+            validator.issue(
+                "application.service_name",
+                "must not be empty while check_local_service is enabled",
+                "set the name of the PW service, e.g. MICSERVER.EXE",
+            );
+        }
+        if !application.limits_file.is_empty()
+            && let Some(directory) = Path::new(&application.limits_file).parent()
+            && !directory.as_os_str().is_empty()
+            && !directory.is_dir()
+        {
+            validator.issue(
+                "application.limits_file",
+                format!("directory {} does not exist", directory.display()),
+                "create the directory or leave the limits_file empty to keep changes until a restart",
+            );
+        }
+        if application.tls_certificate_file.is_empty() {
+            for (field, file) in [
+                ("application.tls_key_file", &application.tls_key_file),
+                (
+                    "application.tls_client_ca_file",
+                    &application.tls_client_ca_file,
+                ),
+            ] {
+                if !file.is_empty() {
+                    validator.issue(
+                        field,
+                        "is only used together with a tls_certificate_file",
+                        "set the tls_certificate_file to serve HTTPS or leave this empty",
+                    );
+                }
+            }
+        } else {
+            if application.tls_key_file.is_empty() {
+                validator.issue(
+                    "application.tls_key_file",
+                    "must not be empty while a tls_certificate_file is set",
+                    "set the PEM file with the private key of the certificate",
+                );
+            }
+            for (field, file) in [
+                (
+                    "application.tls_certificate_file",
+                    &application.tls_certificate_file,
+                ),
+                ("application.tls_key_file", &application.tls_key_file),
+                (
+                    "application.tls_client_ca_file",
+                    &application.tls_client_ca_file,
+                ),
+            ] {
+                if !file.is_empty() && !Path::new(file).is_file() {
+                    validator.issue(
+                        field,
+                        format!("file {file} does not exist"),
+                        "point it to an existing PEM file",
+                    );
+                }
+            }
+        }
+        validator.positive(
+            "application.request_time_limit_seconds",
+            application.request_time_limit_seconds,
+            "every request would time out, increase it to e.g. 5",
+        );
+        validator.not_negative(
+            "application.audit_log_entries",
+            application.audit_log_entries,
+            "set it to 0 to deactivate the endpoint",
+        );
+    }
+
+    fn validate_database(&self, validator: &mut Validator) {
+        let database = &self.database;
+        match &database.connection_string {
+            Some(connection_string) => {
+                if let Err(err) =
+                    tiberius::Config::from_ado_string(connection_string.expose_secret())
+                {
+                    validator.issue(
+                        "database.connection_string",
+                        format!("is not a valid ADO.NET connection string: {err}"),
+                        "copy the connection string PW uses or remove it to use the individual settings",
+                    );
+                }
+            }
+            None => {
+                if database.hostname.is_empty() {
+                    validator.issue(
+                        "database.hostname",
+                        "must not be empty",
+                        "set the host or named instance of the SQL Server",
+                    );
+                }
+                if !matches!(database.auth_method, DBAuthMethod::Integrated) {
+                    if database.username.is_empty() {
+                        validator.issue(
+                            "database.username",
+                            "must be set for Basic and Windows authentication",
+                            "set the SQL Server login, use Integrated auth or a connection_string",
+                        );
+                    }
+                    if database.password.expose_secret().is_empty() {
+                        validator.issue(
+                            "database.password",
+                            "must be set for Basic and Windows authentication",
+                            "set the password, env:<VARIABLE> or file:<path>",
+                        );
+                    }
+                }
+                if matches!(database.auth_method, DBAuthMethod::Windows)
+                    && cfg!(not(target_os = "windows"))
+                {
+                    validator.issue(
+                        "database.auth_method",
+                        "Windows authentication is only supported on Windows",
+                        "use Basic or Integrated instead",
+                    );
+                }
+            }
+        }
+        for (index, partner) in database.failover_partners.iter().enumerate() {
+            if parse_failover_partner(partner, database.port).is_err() {
+                validator.issue(
+                    &format!("database.failover_partners[{index}]"),
+                    format!("{partner:?} is neither host nor host:port"),
+                    "use e.g. SQLSERVER2 or SQLSERVER2:1433",
+                );
+            }
+        }
+        if database.pool_max_size == 0 {
+            validator.issue(
+                "database.pool_max_size",
+                "must be greater than 0",
+                "the default is 5",
+            );
+        }
+        if database.pool_min_idle > database.pool_max_size {
+            validator.issue(
+                "database.pool_min_idle",
+                "must not be greater than pool_max_size",
+                "set it to 0 to keep no idle connections",
+            );
+        }
+        validator.positive(
+            "database.connection_timeout_seconds",
+            database.connection_timeout_seconds,
+            "the default is 5",
+        );
+        validator.positive(
+            "database.connection_check_interval_seconds",
+            database.connection_check_interval_seconds,
+            "the default is 10",
+        );
+        validator.positive(
+            "database.reconnect_max_backoff_seconds",
+            database.reconnect_max_backoff_seconds,
+            "the default is 60",
+        );
+    }
+
+    fn validate_limits(&self, validator: &mut Validator) {
+        let limits = &self.limits;
+        let hint = "set it to 0 to deactivate the check";
+        validator.not_negative("limits.hi_queue_count", limits.hi_queue_count, hint);
+        validator.not_negative("limits.spool_file_count", limits.spool_file_count, hint);
+        validator.percentage("limits.max_cpu_percentage", limits.max_cpu_percentage);
+        validator.percentage("limits.max_ram_percentage", limits.max_ram_percentage);
+        validator.not_negative(
+            "limits.min_connected_workstations",
+            limits.min_connected_workstations,
+            hint,
+        );
+        validator.not_negative(
+            "limits.max_failed_login_attempts",
+            limits.max_failed_login_attempts,
+            hint,
+        );
+        if limits.max_failed_login_attempts != 0 && self.metrics.failed_login_window_minutes == 0 {
+            validator.issue(
+                "metrics.failed_login_window_minutes",
+                "must be greater than 0 while limits.max_failed_login_attempts is set",
+                "the failed login check counts the attempts within this window, e.g. 60",
+            );
+        }
+        validator.not_negative(
+            "limits.max_event_log_age_days",
+            limits.max_event_log_age_days,
+            hint,
+        );
+        validator.not_negative(
+            "limits.max_days_since_last_archive",
+            limits.max_days_since_last_archive,
+            hint,
+        );
+        validator.not_negative(
+            "limits.max_blocked_sessions",
+            limits.max_blocked_sessions,
+            hint,
+        );
+        validator.not_negative(
+            "limits.max_long_running_requests",
+            limits.max_long_running_requests,
+            hint,
+        );
+        validator.not_negative(
+            "limits.max_full_backup_age_hours",
+            limits.max_full_backup_age_hours,
+            hint,
+        );
+        validator.not_negative(
+            "limits.max_differential_backup_age_hours",
+            limits.max_differential_backup_age_hours,
+            hint,
+        );
+        validator.not_negative(
+            "limits.max_log_backup_age_hours",
+            limits.max_log_backup_age_hours,
+            hint,
+        );
+    }
+
+    fn validate_auth(&self, validator: &mut Validator) {
+        let auth = &self.auth;
+        let names = auth
+            .tokens
+            .iter()
+            .map(|token| ("tokens", &token.name))
+            .chain(auth.users.iter().map(|user| ("users", &user.name)));
+        let mut seen = Vec::new();
+        for (list, name) in names {
+            let index = seen.iter().filter(|(other, _)| *other == list).count();
+            if name.is_empty() {
+                validator.issue(
+                    &format!("auth.{list}[{index}].name"),
+                    "must not be empty",
+                    "the name is logged as the author of a change",
+                );
+            } else if seen.iter().any(|(_, other)| *other == name) {
+                validator.issue(
+                    &format!("auth.{list}[{index}].name"),
+                    format!("{name:?} is used by more than one token or user"),
+                    "give each token and user its own name",
+                );
+            }
+            seen.push((list, name));
+        }
+        for (index, token) in auth.tokens.iter().enumerate() {
+            if token.sha256.len() != 64 || !token.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                validator.issue(
+                    &format!("auth.tokens[{index}].sha256"),
+                    "is not a hex encoded SHA-256 digest",
+                    "configure the output of maedic auth hash-token, never the token itself",
+                );
+            }
+        }
+        for (index, user) in auth.users.iter().enumerate() {
+            if let Err(err) = PasswordHash::new(&user.password_hash) {
+                validator.issue(
+                    &format!("auth.users[{index}].password_hash"),
+                    format!("is not a password hash: {err}"),
+                    "configure the output of maedic auth hash-password, never the password itself",
+                );
+            }
+        }
+        if !auth.protected_endpoints.is_empty() && auth.tokens.is_empty() && auth.users.is_empty() {
+            validator.issue(
+                "auth.protected_endpoints",
+                "require credentials, but no tokens or users are configured",
+                "add a token or a user, or leave the endpoints open",
+            );
+        }
+    }
+
+    fn validate_maintenance(&self, validator: &mut Validator) {
+        for (index, window) in self.maintenance.windows.iter().enumerate() {
+            let field = |name: &str| format!("maintenance.windows[{index}].{name}");
+            if window.name.is_empty() {
+                validator.issue(
+                    &field("name"),
+                    "must not be empty",
+                    "the name is exported with the maedic_maintenance_window_active metric",
+                );
+            }
+            match (&window.schedule, &window.start, &window.end) {
+                (Some(schedule), None, None) => {
+                    if let Err(err) = parse_schedule(schedule) {
+                        validator.issue(
+                            &field("schedule"),
+                            format!("{schedule:?} is not a valid cron expression: {err}"),
+                            "use minute hour day month weekday, e.g. 0 22 * * SAT",
+                        );
+                    }
+                    if window.duration_minutes == 0 {
+                        validator.issue(
+                            &field("duration_minutes"),
+                            "must be greater than 0 for a scheduled window",
+                            "set the length of each window, e.g. 120",
+                        );
+                    }
+                }
+                (None, Some(start), Some(end)) => {
+                    match (parse_timestamp(start), parse_timestamp(end)) {
+                        (Ok(start), Ok(end)) => {
+                            if end <= start {
+                                validator.issue(
+                                    &field("end"),
+                                    "must be after the start",
+                                    "swap the start and the end",
+                                );
+                            }
+                        }
+                        (start_result, end_result) => {
+                            for (name, timestamp, result) in
+                                [("start", start, start_result), ("end", end, end_result)]
+                            {
+                                if result.is_err() {
+                                    validator.issue(
+                                        &field(name),
+                                        format!("{timestamp:?} is not a RFC 3339 timestamp"),
+                                        "use e.g. 2026-11-01T20:00:00+01:00",
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => validator.issue(
+                    &format!("maintenance.windows[{index}]"),
+                    "needs either a schedule or a start and an end",
+                    "use a schedule with a duration_minutes for recurring windows",
+                ),
+            }
+        }
+    }
+
+    fn validate_metrics(&self, validator: &mut Validator) {
+        let metrics = &self.metrics;
+        let hint = "set it to 0 to deactivate the collection";
+        validator.not_negative(
+            "metrics.access_event_window_minutes",
+            metrics.access_event_window_minutes,
+            hint,
+        );
+        validator.not_negative(
+            "metrics.access_event_top_n",
+            metrics.access_event_top_n,
+            hint,
+        );
+        validator.not_negative(
+            "metrics.session_stale_minutes",
+            metrics.session_stale_minutes,
+            hint,
+        );
+        validator.not_negative(
+            "metrics.workstation_inactive_days",
+            metrics.workstation_inactive_days,
+            hint,
+        );
+        validator.not_negative(
+            "metrics.database_session_top_n",
+            metrics.database_session_top_n,
+            hint,
+        );
+        validator.not_negative(
+            "metrics.failed_login_window_minutes",
+            metrics.failed_login_window_minutes,
+            hint,
+        );
+        validator.not_negative(
+            "metrics.audit_window_minutes",
+            metrics.audit_window_minutes,
+            hint,
+        );
+        validator.not_negative(
+            "metrics.long_running_request_seconds",
+            metrics.long_running_request_seconds,
+            hint,
+        );
+        validator.not_negative("metrics.wait_stats_top_n", metrics.wait_stats_top_n, hint);
+        validator.not_negative(
+            "metrics.capacity_sample_interval_minutes",
+            metrics.capacity_sample_interval_minutes,
+            hint,
+        );
+        validator.not_negative(
+            "metrics.capacity_history_days",
+            metrics.capacity_history_days,
+            hint,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use secrecy::SecretString;
+
+    use crate::{
+        auth::hash_token,
+        configuration::{
+            AuthSettings, DBAuthMethod, MaintenanceWindow, Settings, TokenCredential,
+            UserCredential, get_settings,
+        },
+        metrics::Endpoint,
+        validation::is_valid_log_level,
+    };
+
+    fn fields(settings: &Settings) -> Vec<String> {
+        match settings.validate() {
+            Ok(()) => Vec::new(),
+            Err(err) => err.0.into_iter().map(|issue| issue.field).collect(),
+        }
+    }
+
+    #[rstest]
+    #[case("test")]
+    #[case("base")]
+    fn shipped_configurations_are_valid(#[case] configname: String) {
+        let settings = get_settings(configname).unwrap();
+        assert_eq!(fields(&settings), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_issue_is_collected() {
+        let mut settings = get_settings("test".to_string()).unwrap();
+        settings.application.log_level = "loud".to_string();
+        settings.limits.hi_queue_count = -1;
+        settings.limits.max_cpu_percentage = f32::NAN;
+        settings.metrics.wait_stats_top_n = -10;
+        settings.database.failover_partners = vec!["SQLSERVER2:abc".to_string()];
+        assert_eq!(
+            fields(&settings),
+            vec![
+                "application.log_level",
+                "database.failover_partners[0]",
+                "limits.hi_queue_count",
+                "limits.max_cpu_percentage",
+                "metrics.wait_stats_top_n",
+            ]
+        );
+    }
+
+    #[test]
+    fn issues_name_the_field_and_a_hint() {
+        let mut settings = get_settings("test".to_string()).unwrap();
+        settings.limits.spool_file_count = -5;
+        let err = settings.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid configuration:\n  limits.spool_file_count: must not be negative, got -5 (set it to 0 to deactivate the check)"
+        );
+    }
+
+    #[rstest]
+    #[case("info", true)]
+    #[case("DEBUG", true)]
+    #[case("warn,maedic=debug", true)]
+    #[case("loud", false)]
+    #[case("", false)]
+    #[case("info,maedic=loud", false)]
+    fn log_levels_are_validated(#[case] log_level: &str, #[case] valid: bool) {
+        assert_eq!(is_valid_log_level(log_level), valid);
+    }
+
+    #[test]
+    fn credentials_need_a_unique_name_and_a_hash() {
+        let mut settings = get_settings("test".to_string()).unwrap();
+        settings.auth = AuthSettings {
+            tokens: vec![
+                TokenCredential {
+                    name: "ops".to_string(),
+                    sha256: hash_token("secret"),
+                },
+                TokenCredential {
+                    name: "grafana".to_string(),
+                    sha256: "secret".to_string(),
+                },
+            ],
+            users: vec![UserCredential {
+                name: "ops".to_string(),
+                password_hash: "Charlie 13".to_string(),
+            }],
+            protected_endpoints: vec![Endpoint::Metrics],
+        };
+        assert_eq!(
+            fields(&settings),
+            vec![
+                "auth.users[0].name",
+                "auth.tokens[1].sha256",
+                "auth.users[0].password_hash"
+            ]
+        );
+        settings.auth.tokens.clear();
+        settings.auth.users.clear();
+        assert_eq!(fields(&settings), vec!["auth.protected_endpoints"]);
+    }
+
+    #[test]
+    fn the_failed_login_check_needs_a_window() {
+        let mut settings = get_settings("test".to_string()).unwrap();
+        settings.limits.max_failed_login_attempts = 5;
+        settings.metrics.failed_login_window_minutes = 0;
+        assert_eq!(
+            fields(&settings),
+            vec!["metrics.failed_login_window_minutes"]
+        );
+        settings.limits.max_failed_login_attempts = 0;
+        assert_eq!(fields(&settings), Vec::<String>::new());
+    }
+
+    #[test]
+    fn tls_needs_a_certificate_and_a_key() {
+        let mut settings = get_settings("test".to_string()).unwrap();
+        settings.application.tls_client_ca_file = "settings/test.yaml".to_string();
+        assert_eq!(fields(&settings), vec!["application.tls_client_ca_file"]);
+        settings.application.tls_certificate_file = "settings/test.yaml".to_string();
+        settings.application.tls_key_file = "settings/missing.pem".to_string();
+        assert_eq!(fields(&settings), vec!["application.tls_key_file"]);
+    }
+
+    #[test]
+    fn maintenance_windows_need_a_schedule_or_a_range() {
+        let mut settings = get_settings("test".to_string()).unwrap();
+        settings.maintenance.windows = vec![
+            MaintenanceWindow {
+                name: "patchday".to_string(),
+                schedule: Some("0 22 * * SAT".to_string()),
+                duration_minutes: 120,
+                ..MaintenanceWindow::default()
+            },
+            MaintenanceWindow {
+                name: "upgrade".to_string(),
+                start: Some("2026-11-01T23:00:00+01:00".to_string()),
+                end: Some("2026-11-01T20:00:00+01:00".to_string()),
+                ..MaintenanceWindow::default()
+            },
+            MaintenanceWindow {
+                name: "typo".to_string(),
+                schedule: Some("0 22 * * SATURDAY".to_string()),
+                ..MaintenanceWindow::default()
+            },
+            MaintenanceWindow::default(),
+        ];
+        assert_eq!(
+            fields(&settings),
+            vec![
+                "maintenance.windows[1].end",
+                "maintenance.windows[2].schedule",
+                "maintenance.windows[2].duration_minutes",
+                "maintenance.windows[3].name",
+                "maintenance.windows[3]",
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(DBAuthMethod::Basic, None, vec!["database.username", "database.password"])]
+    #[case(DBAuthMethod::Integrated, None, vec![])]
+    #[case(
+        DBAuthMethod::Basic,
+        Some("Server=tcp:SQLSERVER;User Id=maedic;Password=secret"),
+        vec![]
+    )]
+    fn missing_credentials_are_refused(
+        #[case] auth_method: DBAuthMethod,
+        #[case] connection_string: Option<&str>,
+        #[case] expected: Vec<&str>,
+    ) {
+        let mut settings = get_settings("test".to_string()).unwrap();
+        settings.database.auth_method = auth_method;
+        settings.database.username = String::new();
+        settings.database.password = SecretString::default();
+        settings.database.connection_string = connection_string.map(Into::into);
+        assert_eq!(fields(&settings), expected);
+    }
+
+    #[test]
+    fn windows_auth_is_only_valid_on_windows() {
+        let mut settings = get_settings("test".to_string()).unwrap();
+        settings.database.auth_method = DBAuthMethod::Windows;
+        assert_eq!(
+            fields(&settings).contains(&"database.auth_method".to_string()),
+            cfg!(not(target_os = "windows"))
+        );
+    }
+}
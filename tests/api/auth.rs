@@ -0,0 +1,75 @@
+use crate::api::helpers::TestServer;
+use maedic::{
+    auth::{hash_password, hash_token},
+    configuration::{TokenCredential, UserCredential, get_settings},
+    metrics::Endpoint,
+};
+use reqwest::StatusCode;
+
+#[tokio::test]
+async fn test_protected_endpoints_require_credentials() {
+    // The database is not needed to authenticate
+    let mut settings = get_settings("test".to_string()).expect("Failed to read configuration");
+    settings.database.hostname = "127.0.0.1".to_string();
+    settings.database.port = 1;
+    settings.application.port = 0;
+    settings.auth.tokens = vec![TokenCredential {
+        name: "prometheus".to_string(),
+        sha256: hash_token("Charlie 13"),
+    }];
+    settings.auth.users = vec![UserCredential {
+        name: "ops".to_string(),
+        password_hash: hash_password("Charlie 14").unwrap(),
+    }];
+    settings.auth.protected_endpoints = vec![Endpoint::Metrics, Endpoint::Config];
+
+    let application = TestServer::build(settings)
+        .await
+        .expect("Failed to build Application.");
+    let address = format!("http://127.0.0.1:{}", application.port());
+    let _handle = tokio::spawn(application.run_until_stopped());
+    let client = reqwest::Client::new();
+
+    let anonymous = client
+        .get(format!("{address}/metrics"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+    assert!(anonymous.headers().contains_key("www-authenticate"));
+
+    let wrong_password = client
+        .get(format!("{address}/v1/config"))
+        .basic_auth("ops", Some("Charlie 13"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(wrong_password.status(), StatusCode::UNAUTHORIZED);
+
+    let user = client
+        .get(format!("{address}/v1/config"))
+        .basic_auth("ops", Some("Charlie 14"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(user.status(), StatusCode::OK);
+
+    // The health stays anonymous for the load balancers, it only fails for the missing database
+    let health = client
+        .get(format!("{address}/v1/health"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(health.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let token = client
+        .get(format!("{address}/metrics"))
+        .bearer_auth("Charlie 13")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(token.status(), StatusCode::OK);
+    let metrics = token.text().await.unwrap();
+    assert!(metrics.contains(r#"maedic_auth_failures_total{endpoint="Metrics"} 1"#));
+    assert!(metrics.contains(r#"maedic_auth_failures_total{endpoint="Config"} 1"#));
+}
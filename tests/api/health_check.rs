@@ -1,6 +1,6 @@
-use crate::api::helpers::{DbVersion, TestApplication};
+use crate::api::helpers::{DbVersion, TestApplication, TestServer};
 use maedic::{
-    configuration::LimitSettings,
+    configuration::{LimitSettings, get_settings},
     database::DatabaseConnectionState,
     health::{MaedicHealth, PWHealth},
 };
@@ -45,9 +45,19 @@ async fn test_pw_health_endpoint_works_with_db(#[case] db_version: DbVersion) {
     let perfect_health: PWHealth = PWHealth {
         unhealthy_spool_files: Some(Vec::new()),
         hi_queue_size: Some(0),
+        hi_queue_per_panel: None,
         global_cpu_usage_percentage: None,
         used_memory_percentage: None,
         service_state: None,
+        connected_workstations: None,
+        failed_login_attempts: None,
+        oldest_event_age_days: None,
+        event_log_archive: None,
+        blocked_sessions: None,
+        long_running_requests: None,
+        backups: None,
+        replicas: None,
+        silenced_checks: Vec::new(),
         maedic_health: MaedicHealth {
             database_connection: DatabaseConnectionState::Healthy,
             version_number: env!("CARGO_PKG_VERSION").to_string(),
@@ -95,3 +105,68 @@ async fn test_404_is_sent(#[case] db_version: DbVersion) {
     assert_eq!(response.status(), 404);
     assert_eq!(response.text().await.unwrap(), "nothing to see here");
 }
+
+#[tokio::test]
+#[rstest]
+#[case(DbVersion::V652SP1)]
+#[case(DbVersion::V66SP1)]
+async fn test_audit_endpoint_works(#[case] db_version: DbVersion) {
+    let app = TestApplication::spawn_app(db_version).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/v1/audit", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert!(response.status().is_success());
+    assert_eq!(response.text().await.unwrap(), "[]");
+}
+
+#[tokio::test]
+#[rstest]
+#[case(DbVersion::V652SP1)]
+async fn test_capacity_endpoint_works(#[case] db_version: DbVersion) {
+    let app = TestApplication::spawn_app(db_version).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/v1/capacity", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert!(response.status().is_success());
+    assert!(response.text().await.unwrap().contains("\"samples\""));
+}
+
+#[tokio::test]
+async fn test_health_reports_unreachable_database() {
+    let settings = {
+        let mut c = get_settings("test".to_string()).expect("Failed to read configuration");
+        c.database.hostname = "127.0.0.1".to_string();
+        c.database.port = 1;
+        c.application.port = 0;
+        c
+    };
+    let application = TestServer::build(settings)
+        .await
+        .expect("Failed to build Application.");
+    let address = format!("http://127.0.0.1:{}", application.port());
+    let _handle = tokio::spawn(application.run_until_stopped());
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/v1/health", address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status(), 503);
+    let health: PWHealth = response.json().await.unwrap();
+    assert_eq!(
+        health.maedic_health.database_connection,
+        DatabaseConnectionState::Unhealthy
+    );
+}
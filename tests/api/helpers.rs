@@ -1,19 +1,16 @@
-use axum::Router;
-use axum::extract::ConnectInfo;
-use axum::extract::connect_info::IntoMakeServiceWithConnectInfo;
-use axum::middleware::AddExtension;
-use axum::serve::Serve;
+use maedic::auth::VerifiedPasswords;
+use maedic::capacity::CapacityHistory;
 use maedic::metrics::setup_metrics_registry;
-use maedic::run::run;
+use maedic::reload::ConfigSource;
+use maedic::run::{Server, run};
 use maedic::{
     configuration::{DBConnectionPool, DatabaseSettings, Settings, get_settings},
-    database::setup_database_pool,
+    database::{check_database_connection, setup_database_pool},
     run::AppState,
     telemetry::initialize_tracing,
 };
 use once_cell::sync::Lazy;
 use secrecy::ExposeSecret;
-use std::net::SocketAddr;
 use sysinfo::System;
 use tiberius::{AuthMethod, Client, Config};
 use tokio::net::TcpListener;
@@ -52,7 +49,6 @@ impl TestApplication {
         configure_database(&mut migration_client, db_version.clone()).await;
 
         let pool = setup_database_pool(settings.database.clone())
-            .await
             .expect("Failed to create database connection pool");
 
         let application = TestServer::build(settings.clone())
@@ -74,18 +70,22 @@ impl TestApplication {
 /// `TestServer` which sets up a fresh Database for each test
 pub struct TestServer {
     port: u16,
-    server: Serve<
-        TcpListener,
-        IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
-        AddExtension<Router, ConnectInfo<SocketAddr>>,
-    >,
+    server: Server,
 }
 
 impl TestServer {
     pub async fn build(settings: Settings) -> Result<Self, anyhow::Error> {
+        Self::build_with_config_source(settings, ConfigSource::default()).await
+    }
+
+    /// Build the `TestServer` reloading its settings from the given config files
+    pub async fn build_with_config_source(
+        settings: Settings,
+        config_source: ConfigSource,
+    ) -> Result<Self, anyhow::Error> {
         let connection_pool = setup_database_pool(settings.database.clone())
-            .await
             .expect("Failed to create database connection pool");
+        let database_connection = check_database_connection(connection_pool.clone()).await;
         let listener = TcpListener::bind(format!(
             "{}:{}",
             settings.application.host, settings.application.port
@@ -96,7 +96,7 @@ impl TestServer {
             .local_addr()
             .expect("Failed to create listener")
             .port();
-        let (registry, metrics) = setup_metrics_registry().await;
+        let (registry, metrics) = setup_metrics_registry(&connection_pool).await;
         info!(
             "Starting app on {:?}:{:?}",
             settings.application.host, settings.application.port
@@ -110,6 +110,12 @@ impl TestServer {
                 sys: System::new_all(),
                 registry,
                 metrics,
+                capacity: CapacityHistory::default(),
+                database_connection,
+                config_source,
+                silences: Vec::new(),
+                tls: None,
+                verified_passwords: VerifiedPasswords::default(),
             },
             settings,
         )
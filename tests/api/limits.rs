@@ -0,0 +1,83 @@
+use crate::api::helpers::TestServer;
+use maedic::{
+    auth::hash_token,
+    configuration::{LimitSettings, TokenCredential, get_settings, load_settings},
+};
+use reqwest::StatusCode;
+
+#[tokio::test]
+async fn test_limits_are_changed_by_authenticated_requests_only() {
+    let directory = std::env::temp_dir().join(format!("maedic-limits-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&directory).unwrap();
+    let limits_file = directory.join("limits.yaml");
+    // The database is not needed to change the limits
+    let mut settings = get_settings("test".to_string()).expect("Failed to read configuration");
+    settings.database.hostname = "127.0.0.1".to_string();
+    settings.database.port = 1;
+    settings.application.port = 0;
+    settings.auth.tokens = vec![TokenCredential {
+        name: "ops".to_string(),
+        sha256: hash_token("Charlie 13"),
+    }];
+    settings.application.limits_file = limits_file.display().to_string();
+
+    let application = TestServer::build(settings)
+        .await
+        .expect("Failed to build Application.");
+    let address = format!("http://127.0.0.1:{}/v1/config/limits", application.port());
+    let _handle = tokio::spawn(application.run_until_stopped());
+    let client = reqwest::Client::new();
+
+    let unauthorized = client
+        .patch(&address)
+        .json(&serde_json::json!({ "hi_queue_count": 5 }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+
+    let invalid = client
+        .patch(&address)
+        .bearer_auth("Charlie 13")
+        .json(&serde_json::json!({ "max_cpu_percentage": 120.0 }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(invalid.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(
+        invalid
+            .text()
+            .await
+            .unwrap()
+            .contains("limits.max_cpu_percentage")
+    );
+
+    let unknown = client
+        .patch(&address)
+        .bearer_auth("Charlie 13")
+        .json(&serde_json::json!({ "hi_queue_counts": 5 }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(unknown.status(), StatusCode::BAD_REQUEST);
+
+    let changed = client
+        .patch(&address)
+        .bearer_auth("Charlie 13")
+        .json(&serde_json::json!({ "hi_queue_count": 5 }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(changed.status(), StatusCode::OK);
+    let limits: LimitSettings = changed.json().await.unwrap();
+    assert_eq!(limits.hi_queue_count, 5);
+    assert_eq!(limits.spool_file_count, 10);
+
+    // Only the changed limit overrides the config files
+    let written = std::fs::read_to_string(&limits_file).unwrap();
+    assert!(written.contains("\n  hi_queue_count: 5"));
+    assert!(written.contains("\n  # spool_file_count:"));
+    let persisted = load_settings(&["settings/test.yaml".into(), limits_file]);
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(persisted.unwrap().limits, limits);
+}
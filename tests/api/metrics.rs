@@ -150,3 +150,152 @@ async fn test_metrics_content_type(#[case] db_version: DbVersion) {
         .expect("Failed to get content_type from header");
     assert_eq!(content_type, "text/plain; version=0.0.4; charset=utf-8");
 }
+
+#[tokio::test]
+#[rstest]
+#[case(DbVersion::V652SP1)]
+#[case(DbVersion::V66SP1)]
+async fn test_metrics_access_events(#[case] db_version: DbVersion) {
+    let app = TestApplication::spawn_app(db_version).await;
+    let client = TestClient::new();
+
+    let response = client.get_endpoint(app.address, "/metrics").await;
+
+    assert!(response.status().is_success());
+    let text = response
+        .text()
+        .await
+        .expect("Could not convert response to text");
+
+    assert!(text.contains("# TYPE maedic_access_events_per_device gauge"));
+    assert!(text.contains("# TYPE maedic_access_events_per_panel gauge"));
+}
+
+#[tokio::test]
+#[rstest]
+#[case(DbVersion::V652SP1)]
+#[case(DbVersion::V66SP1)]
+async fn test_metrics_sessions(#[case] db_version: DbVersion) {
+    let app = TestApplication::spawn_app(db_version).await;
+    let client = TestClient::new();
+
+    let response = client.get_endpoint(app.address, "/metrics").await;
+
+    assert!(response.status().is_success());
+    let text = response
+        .text()
+        .await
+        .expect("Could not convert response to text");
+
+    assert!(text.contains("# TYPE maedic_client_sessions gauge"));
+    assert!(text.contains("maedic_workstations_inactive 0"));
+    assert!(text.contains("maedic_database_sessions{host="));
+}
+
+#[tokio::test]
+#[rstest]
+#[case(DbVersion::V652SP1)]
+#[case(DbVersion::V66SP1)]
+async fn test_metrics_failed_logins(#[case] db_version: DbVersion) {
+    let app = TestApplication::spawn_app(db_version).await;
+    let client = TestClient::new();
+
+    let response = client.get_endpoint(app.address, "/metrics").await;
+
+    assert!(response.status().is_success());
+    let text = response
+        .text()
+        .await
+        .expect("Could not convert response to text");
+
+    assert!(text.contains("# TYPE maedic_failed_logins gauge"));
+    assert!(text.contains("maedic_locked_operator_accounts 0"));
+}
+
+#[tokio::test]
+#[rstest]
+#[case(DbVersion::V652SP1)]
+#[case(DbVersion::V66SP1)]
+async fn test_metrics_event_log_retention(#[case] db_version: DbVersion) {
+    let app = TestApplication::spawn_app(db_version).await;
+    let client = TestClient::new();
+
+    let response = client.get_endpoint(app.address, "/metrics").await;
+
+    assert!(response.status().is_success());
+    let text = response
+        .text()
+        .await
+        .expect("Could not convert response to text");
+
+    assert!(text.contains("maedic_event_log_oldest_event_age_days 0"));
+    assert!(text.contains("maedic_event_log_events_last_day 0"));
+    assert!(text.contains("maedic_event_log_days_since_last_archive -1"));
+    assert!(text.contains("maedic_event_log_last_archive_failed 0"));
+}
+
+#[tokio::test]
+#[rstest]
+#[case(DbVersion::V652SP1)]
+#[case(DbVersion::V66SP1)]
+async fn test_metrics_mssql(#[case] db_version: DbVersion) {
+    let app = TestApplication::spawn_app(db_version).await;
+    let client = TestClient::new();
+
+    let response = client.get_endpoint(app.address, "/metrics").await;
+
+    assert!(response.status().is_success());
+    let text = response
+        .text()
+        .await
+        .expect("Could not convert response to text");
+
+    assert!(text.contains("maedic_mssql_info{version="));
+    assert!(text.contains("maedic_mssql_database_state{state=\"ONLINE\"} 1"));
+    assert!(text.contains("maedic_mssql_file_size_megabytes{file="));
+    assert!(text.contains("file_type=\"LOG\""));
+    assert!(text.contains("# TYPE maedic_mssql_blocked_sessions gauge"));
+}
+
+#[tokio::test]
+#[rstest]
+#[case(DbVersion::V652SP1)]
+#[case(DbVersion::V66SP1)]
+async fn test_metrics_backups(#[case] db_version: DbVersion) {
+    let app = TestApplication::spawn_app(db_version).await;
+    let client = TestClient::new();
+
+    let response = client.get_endpoint(app.address, "/metrics").await;
+
+    assert!(response.status().is_success());
+    let text = response
+        .text()
+        .await
+        .expect("Could not convert response to text");
+
+    assert!(text.contains("maedic_mssql_backup_age_hours{backup_type=\"Full\"} -1"));
+    assert!(text.contains("maedic_mssql_backup_permission_denied 0"));
+}
+
+#[tokio::test]
+#[rstest]
+#[case(DbVersion::V652SP1)]
+#[case(DbVersion::V66SP1)]
+async fn test_metrics_pool_and_query_latency(#[case] db_version: DbVersion) {
+    let app = TestApplication::spawn_app(db_version).await;
+    let client = TestClient::new();
+
+    let response = client.get_endpoint(app.address, "/metrics").await;
+
+    assert!(response.status().is_success());
+    let text = response
+        .text()
+        .await
+        .expect("Could not convert response to text");
+
+    assert!(text.contains("maedic_pool_connections"));
+    assert!(text.contains("maedic_pool_get_timeouts_total 0"));
+    assert!(
+        text.contains("maedic_database_query_duration_seconds_count{query=\"Check Table Size\"}")
+    );
+}
@@ -1,4 +1,9 @@
+mod auth;
 mod health_check;
 mod helpers;
+mod limits;
 mod metrics;
 mod rate_limit;
+mod reload;
+mod silences;
+mod tls;
@@ -0,0 +1,73 @@
+use crate::api::helpers::TestServer;
+use maedic::{
+    configuration::{LimitSettings, load_settings},
+    reload::ConfigSource,
+};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_changed_config_files_are_reloaded() {
+    let directory = std::env::temp_dir().join(format!("maedic-reload-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&directory).unwrap();
+    let config_file = directory.join("base.yaml");
+    // The database is not needed to reload the configuration
+    let config = include_str!("../../settings/test.yaml")
+        .replace("hostname: localhost", "hostname: 127.0.0.1")
+        .replace("port: 1433", "port: 1")
+        .replace("port: 3000", "port: 0");
+    std::fs::write(&config_file, &config).unwrap();
+    let settings = load_settings(std::slice::from_ref(&config_file)).unwrap();
+
+    let application = TestServer::build_with_config_source(
+        settings,
+        ConfigSource {
+            files: vec![config_file.clone()],
+            log_level: None,
+            log_level_override: None,
+        },
+    )
+    .await
+    .expect("Failed to build Application.");
+    let address = format!("http://127.0.0.1:{}", application.port());
+    let _handle = tokio::spawn(application.run_until_stopped());
+    let client = reqwest::Client::new();
+
+    std::fs::write(
+        &config_file,
+        config
+            .replace("hi_queue_count: 1000", "hi_queue_count: 5")
+            .replace("port: 0", "port: 4000"),
+    )
+    .unwrap();
+
+    let mut limits = None;
+    // Polling slower than the rate limit
+    for _ in 0..10 {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let response: LimitSettings = client
+            .get(format!("{}/v1/config", address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .json()
+            .await
+            .unwrap();
+        if response.hi_queue_count == 5 {
+            limits = Some(response);
+            break;
+        }
+    }
+    assert!(limits.is_some(), "the changed limit was not reloaded");
+
+    let metrics = client
+        .get(format!("{}/metrics", address))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert!(metrics.contains("maedic_config_reloads_total{result=\"Success\"}"));
+    assert!(metrics.contains("maedic_config_restart_required 1"));
+}
@@ -0,0 +1,87 @@
+use crate::api::helpers::TestServer;
+use maedic::{
+    auth::hash_token,
+    configuration::{TokenCredential, get_settings},
+    health::{HealthCheck, PWHealth},
+    silence::Silence,
+};
+use reqwest::StatusCode;
+
+#[tokio::test]
+async fn test_silenced_checks_do_not_affect_the_status_code() {
+    // Without a database the database_connection check fails
+    let mut settings = get_settings("test".to_string()).expect("Failed to read configuration");
+    settings.database.hostname = "127.0.0.1".to_string();
+    settings.database.port = 1;
+    settings.application.port = 0;
+    settings.auth.tokens = vec![TokenCredential {
+        name: "ops".to_string(),
+        sha256: hash_token("Charlie 13"),
+    }];
+
+    let application = TestServer::build(settings)
+        .await
+        .expect("Failed to build Application.");
+    let address = format!("http://127.0.0.1:{}", application.port());
+    let _handle = tokio::spawn(application.run_until_stopped());
+    let client = reqwest::Client::new();
+
+    let unauthorized = client
+        .post(format!("{address}/v1/silences"))
+        .json(&serde_json::json!({ "checks": ["database_connection"], "duration_minutes": 30 }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+
+    let created = client
+        .post(format!("{address}/v1/silences"))
+        .bearer_auth("Charlie 13")
+        .json(&serde_json::json!({
+            "checks": ["database_connection"],
+            "duration_minutes": 30,
+            "comment": "database upgrade"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(created.status(), StatusCode::CREATED);
+    let silence: Silence = created.json().await.unwrap();
+    assert_eq!(silence.created_by, "ops");
+
+    let health = client
+        .get(format!("{address}/v1/health"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(health.status(), StatusCode::OK);
+    let health: PWHealth = health.json().await.unwrap();
+    assert_eq!(
+        health.silenced_checks,
+        vec![HealthCheck::DatabaseConnection]
+    );
+
+    let metrics = client
+        .get(format!("{address}/metrics"))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains(&format!(
+        "maedic_silence_expiry_timestamp_seconds{{id=\"{}\",created_by=\"ops\",checks=\"database_connection\",channels=\"\",panels=\"\"}} {}",
+        silence.id,
+        silence.expires_at.timestamp()
+    )));
+
+    // Staying within the rate limit of 5 requests
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    let removed = client
+        .delete(format!("{address}/v1/silences/{}", silence.id))
+        .bearer_auth("Charlie 13")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(removed.status(), StatusCode::NO_CONTENT);
+}
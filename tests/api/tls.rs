@@ -0,0 +1,131 @@
+use crate::api::helpers::TestServer;
+use maedic::{configuration::get_settings, metrics::Endpoint};
+use rcgen::{
+    BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair,
+    date_time_ymd,
+};
+use reqwest::{Certificate, Client, Identity, StatusCode};
+use std::path::Path;
+
+/// A certificate for `localhost` signed by the `issuer`, valid until the first of January of `year`
+fn certificate(
+    issuer: &Issuer<'_, KeyPair>,
+    purpose: ExtendedKeyUsagePurpose,
+    year: i32,
+) -> (String, String) {
+    let key = KeyPair::generate().unwrap();
+    let mut params =
+        CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()]).unwrap();
+    params.extended_key_usages = vec![purpose];
+    params.not_after = date_time_ymd(year, 1, 1);
+    let certificate = params.signed_by(&key, issuer).unwrap();
+    (certificate.pem(), key.serialize_pem())
+}
+
+/// The value of the `maedic_tls_certificate_expiry_days` metric
+async fn expiry_days(client: &Client, address: &str) -> f64 {
+    let metrics = client
+        .get(format!("{address}/metrics"))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .unwrap();
+    metrics
+        .lines()
+        .find(|line| line.starts_with("maedic_tls_certificate_expiry_days{"))
+        .and_then(|line| line.rsplit(' ').next())
+        .and_then(|days| days.parse().ok())
+        .expect("The expiry of the certificate is exported")
+}
+
+fn write(directory: &Path, name: &str, content: &str) -> String {
+    let path = directory.join(name);
+    std::fs::write(&path, content).unwrap();
+    path.display().to_string()
+}
+
+#[tokio::test]
+async fn test_https_requires_a_client_certificate_and_reloads_the_certificate() {
+    let directory = std::env::temp_dir().join(format!("maedic-tls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&directory).unwrap();
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca_certificate = ca_params.self_signed(&ca_key).unwrap().pem();
+    let issuer = Issuer::new(ca_params, ca_key);
+    let (server_certificate, server_key) =
+        certificate(&issuer, ExtendedKeyUsagePurpose::ServerAuth, 2031);
+    let (client_certificate, client_key) =
+        certificate(&issuer, ExtendedKeyUsagePurpose::ClientAuth, 2031);
+
+    // The database is not needed to serve HTTPS
+    let mut settings = get_settings("test".to_string()).expect("Failed to read configuration");
+    settings.database.hostname = "127.0.0.1".to_string();
+    settings.database.port = 1;
+    settings.application.host = "127.0.0.1".to_string();
+    settings.application.port = 0;
+    settings.application.tls_certificate_file = write(&directory, "cert.pem", &server_certificate);
+    settings.application.tls_key_file = write(&directory, "key.pem", &server_key);
+    settings.application.tls_client_ca_file = write(&directory, "ca.pem", &ca_certificate);
+    settings.application.tls_anonymous_endpoints = vec![Endpoint::Health];
+
+    let application = TestServer::build(settings)
+        .await
+        .expect("Failed to build Application.");
+    let port = application.port();
+    let _handle = tokio::spawn(application.run_until_stopped());
+    let address = format!("https://localhost:{port}");
+    let ca = Certificate::from_pem(ca_certificate.as_bytes()).unwrap();
+
+    let anonymous = Client::builder()
+        .tls_certs_only([ca.clone()])
+        .build()
+        .unwrap();
+    // Only the anonymous endpoints are answered without a client certificate
+    let health = anonymous
+        .get(format!("{address}/v1/health"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(health.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let metrics = anonymous
+        .get(format!("{address}/metrics"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(metrics.status(), StatusCode::FORBIDDEN);
+    assert!(
+        reqwest::get(format!("http://localhost:{port}/v1/health"))
+            .await
+            .is_err()
+    );
+
+    let client = Client::builder()
+        .tls_certs_only([ca])
+        .identity(
+            Identity::from_pem(format!("{client_certificate}{client_key}").as_bytes()).unwrap(),
+        )
+        .build()
+        .unwrap();
+    let health = client
+        .get(format!("{address}/v1/health"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(health.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let expiry = expiry_days(&client, &address).await;
+    assert!(expiry > 0.0);
+
+    // A renewed certificate is picked up without a restart
+    let (renewed_certificate, renewed_key) =
+        certificate(&issuer, ExtendedKeyUsagePurpose::ServerAuth, 2032);
+    write(&directory, "key.pem", &renewed_key);
+    write(&directory, "cert.pem", &renewed_certificate);
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    let renewed_expiry = expiry_days(&client, &address).await;
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert!(renewed_expiry - expiry > 364.0);
+}
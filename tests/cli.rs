@@ -20,9 +20,235 @@ mod cli {
     async fn test_other_subcommand_refused() {
         let mut cmd = cargo_bin_cmd!();
         cmd.arg("wrong")
+            .assert()
+            .failure()
+            .code(2)
+            .stderr(contains("unrecognized subcommand 'wrong'".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_help_lists_subcommands() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.arg("--help")
             .assert()
             .success()
-            .code(0)
-            .stdout(contains("only other supported subcommand".to_string()));
+            .stdout(contains("serve"))
+            .stdout(contains("check"))
+            .stdout(contains("metrics"))
+            .stdout(contains("--config <PATH>"))
+            .stdout(contains("--log-level <LEVEL>"));
+    }
+
+    #[tokio::test]
+    async fn test_config_print_default_prints_a_valid_config() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["config", "print-default"])
+            .assert()
+            .success()
+            .stdout(contains("application:"))
+            .stdout(contains("  trust_cert: true # "))
+            .stdout(contains("  # connection_string: # "));
+    }
+
+    #[tokio::test]
+    async fn test_config_init_writes_a_valid_config() {
+        let directory = std::env::temp_dir().join(format!("maedic-init-{}", uuid::Uuid::new_v4()));
+        let path = directory.join("base.yaml");
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["config", "init"]).arg(&path).assert().success();
+        let config = std::fs::read_to_string(&path).unwrap();
+        assert!(config.contains("  username: \"\""));
+        std::fs::write(
+            &path,
+            config
+                .replace("  username: \"\"", "  username: \"maedic\"")
+                .replace("  password: \"\"", "  password: \"Charlie 13\""),
+        )
+        .unwrap();
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["config", "validate", "--config"])
+            .arg(&directory)
+            .assert()
+            .success()
+            .stdout(contains("Configuration is valid"));
+        let mut cmd = cargo_bin_cmd!();
+        let refused = cmd.args(["config", "init"]).arg(&path).assert();
+        std::fs::remove_dir_all(&directory).unwrap();
+        refused
+            .failure()
+            .code(1)
+            .stderr(contains("already exists, use --force to overwrite it"));
+    }
+
+    #[tokio::test]
+    async fn test_config_migrate_upgrades_an_older_config() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["config", "migrate", "tests/bad_config.yaml"])
+            .assert()
+            .success()
+            .stdout(contains("  trust_cert: true # "))
+            .stdout(contains("  hostname: \"localhost\""))
+            .stderr(contains("added database.trust_cert with its default value"))
+            .stderr(contains("removed unknown key database.trust_certs"));
+    }
+
+    #[tokio::test]
+    async fn test_config_validate_warns_about_unknown_keys() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["config", "validate", "--config", "tests/bad_config.yaml"])
+            .assert()
+            .success()
+            .stderr(contains(
+                "unknown configuration field \"database.trust_certs\"",
+            ));
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["config", "validate", "--config", "tests/bad_config.yaml"])
+            .env("MAEDIC__APPLICATION__STRICT_CONFIG", "true")
+            .assert()
+            .failure()
+            .code(1)
+            .stderr(contains(
+                "unknown configuration field \"database.trust_certs\"",
+            ));
+    }
+
+    #[tokio::test]
+    async fn test_auth_hash_token_prints_the_digest() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["auth", "hash-token"])
+            .write_stdin("test\n")
+            .assert()
+            .success()
+            .stdout(contains(
+                "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+            ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_log_level_refused() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["config", "validate", "--config", "settings/test.yaml"])
+            .args(["--log-level", "loud"])
+            .assert()
+            .failure()
+            .code(1)
+            .stderr(contains("application.log_level"));
+    }
+
+    #[tokio::test]
+    async fn test_db_test_connection_fails_without_database() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["db", "test-connection", "--config", "settings/test.yaml"])
+            .env("MAEDIC__DATABASE__HOSTNAME", "127.0.0.1")
+            .env("MAEDIC__DATABASE__PORT", "1")
+            .assert()
+            .failure()
+            .code(1)
+            .stderr(contains("Could not connect to the database"));
+    }
+
+    #[tokio::test]
+    async fn test_check_reports_unhealthy_without_database() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["check", "--config", "settings/test.yaml"])
+            .env("MAEDIC__DATABASE__HOSTNAME", "127.0.0.1")
+            .env("MAEDIC__DATABASE__PORT", "1")
+            .assert()
+            .failure()
+            .code(1)
+            .stdout(contains("\"database_connection\": \"Unhealthy\""));
+    }
+
+    #[tokio::test]
+    async fn test_serve_fails_on_a_port_in_use() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["serve", "--config", "settings/test.yaml"])
+            .env("MAEDIC__APPLICATION__HOST", "127.0.0.1")
+            .env("MAEDIC__APPLICATION__PORT", port.to_string())
+            .env(
+                "MAEDIC__APPLICATION__LOGFILE_PATH",
+                std::env::temp_dir().join("maedic-port-in-use.log"),
+            )
+            .assert()
+            .failure()
+            .code(1)
+            .stderr(contains(format!("Could not bind to 127.0.0.1:{port}")));
+    }
+
+    #[tokio::test]
+    async fn test_missing_config_file_refused() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["--config", "settings/surely_missing.yaml"])
+            .assert()
+            .failure()
+            .stderr(contains("settings/surely_missing.yaml does not exist"));
+    }
+
+    #[tokio::test]
+    async fn test_config_validate_accepts_valid_config() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args(["config", "validate", "--config", "settings/test.yaml"])
+            .assert()
+            .success()
+            .stdout(contains("Configuration is valid"));
+    }
+
+    #[tokio::test]
+    async fn test_config_validate_lists_every_issue() {
+        let path = std::env::temp_dir().join(format!("maedic-invalid-{}.yaml", std::process::id()));
+        std::fs::write(
+            &path,
+            include_str!("../settings/test.yaml")
+                .replace("log_level: info", "log_level: loud")
+                .replace("hi_queue_count: 1000", "hi_queue_count: -1"),
+        )
+        .unwrap();
+        let mut cmd = cargo_bin_cmd!();
+        let assert = cmd
+            .args(["config", "validate", "--config"])
+            .arg(&path)
+            .assert();
+        std::fs::remove_file(&path).unwrap();
+        assert
+            .failure()
+            .code(1)
+            .stderr(contains("application.log_level"))
+            .stderr(contains("limits.hi_queue_count"));
+    }
+
+    #[tokio::test]
+    async fn test_check_nagios_reports_critical_without_database() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args([
+            "check",
+            "--format",
+            "nagios",
+            "--config",
+            "settings/test.yaml",
+        ])
+        .env("MAEDIC__DATABASE__HOSTNAME", "127.0.0.1")
+        .env("MAEDIC__DATABASE__PORT", "1")
+        .assert()
+        .code(2)
+        .stdout(contains(
+            "MAEDIC CRITICAL - failed checks: database_connection",
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_nagios_reports_unknown_on_invalid_config() {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.args([
+            "check",
+            "--format",
+            "nagios",
+            "--config",
+            "settings/surely_missing.yaml",
+        ])
+        .assert()
+        .code(3)
+        .stdout(contains("MAEDIC UNKNOWN - "));
     }
 }